@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE member_relationships;
+
+ALTER TABLE members DROP COLUMN household_limit;
+
+UPDATE members SET email = '' WHERE email IS NULL;
+ALTER TABLE members ALTER COLUMN email SET NOT NULL;
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+
+-- children's accounts are managed through a guardian and need no email of their own
+ALTER TABLE members ALTER COLUMN email DROP NOT NULL;
+
+-- optional cap on the number of open loans across a guardian and their dependents
+ALTER TABLE members ADD COLUMN household_limit INT;
+
+CREATE TABLE member_relationships (
+    guardian_id UUID NOT NULL,
+    dependent_id UUID NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (guardian_id, dependent_id),
+    CHECK (guardian_id <> dependent_id),
+    FOREIGN KEY (guardian_id) REFERENCES members (member_id),
+    FOREIGN KEY (dependent_id) REFERENCES members (member_id)
+);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE members ADD COLUMN anonymized_at TIMESTAMP;
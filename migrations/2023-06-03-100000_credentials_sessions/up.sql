@@ -0,0 +1,25 @@
+-- Your SQL goes here
+
+CREATE TABLE credentials (
+    member_id UUID PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (member_id) REFERENCES members (member_id)
+);
+
+SELECT diesel_manage_updated_at('credentials');
+
+CREATE TABLE sessions (
+    session_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    member_id UUID NOT NULL,
+    -- sha-256 of the bearer token, the token itself is never stored
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    FOREIGN KEY (member_id) REFERENCES members (member_id)
+);
+
+CREATE INDEX sessions_member_id_idx ON sessions (member_id);
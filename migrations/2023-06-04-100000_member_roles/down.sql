@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE members DROP COLUMN role;
+
+DROP TYPE IF EXISTS member_role;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+CREATE TYPE member_role AS ENUM ('patron', 'circulation', 'cataloger', 'admin');
+
+-- Everybody starts out as a patron. The first administrator is created on
+-- startup from ADMIN_USERNAME and ADMIN_PASSWORD.
+ALTER TABLE members ADD COLUMN role member_role NOT NULL DEFAULT 'patron';
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+
+CREATE TABLE api_keys (
+    key_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name TEXT NOT NULL,
+    -- first characters of the key, shown to tell keys apart
+    prefix TEXT NOT NULL,
+    -- sha-256 of the key, the key itself is only shown once when issued
+    key_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_by UUID,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP,
+    revoked_at TIMESTAMP,
+    last_used_at TIMESTAMP,
+    FOREIGN KEY (created_by) REFERENCES members (member_id) ON DELETE SET NULL
+);
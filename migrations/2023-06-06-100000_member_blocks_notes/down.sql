@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE member_notes;
+
+DROP TABLE member_blocks;
@@ -0,0 +1,29 @@
+-- Your SQL goes here
+
+CREATE TABLE member_blocks (
+    block_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    member_id UUID NOT NULL,
+    -- short category such as 'unpaid_damage' or 'id_not_verified'
+    block_type TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    created_by UUID,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP,
+    lifted_at TIMESTAMP,
+    FOREIGN KEY (member_id) REFERENCES members (member_id),
+    FOREIGN KEY (created_by) REFERENCES members (member_id) ON DELETE SET NULL
+);
+
+CREATE INDEX member_blocks_member_id_idx ON member_blocks (member_id);
+
+CREATE TABLE member_notes (
+    note_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    member_id UUID NOT NULL,
+    body TEXT NOT NULL,
+    created_by UUID,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (member_id) REFERENCES members (member_id),
+    FOREIGN KEY (created_by) REFERENCES members (member_id) ON DELETE SET NULL
+);
+
+CREATE INDEX member_notes_member_id_idx ON member_notes (member_id);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE members DROP COLUMN expires_on;
+ALTER TABLE members DROP COLUMN tier;
+ALTER TABLE members DROP COLUMN external_id;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+-- identifier assigned by the registrar, used to match rows of the import file
+ALTER TABLE members ADD COLUMN external_id TEXT UNIQUE;
+ALTER TABLE members ADD COLUMN tier TEXT NOT NULL DEFAULT 'standard';
+-- members are refused new loans after this date
+ALTER TABLE members ADD COLUMN expires_on DATE;
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+
+-- audit trail of duplicate accounts folded into another member
+CREATE TABLE member_merges (
+    merge_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    primary_id UUID NOT NULL,
+    -- the merged account no longer exists, so this is not a foreign key
+    merged_member_id UUID NOT NULL,
+    merged_by UUID,
+    merged_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- the merged member row as it was before the merge
+    snapshot JSONB NOT NULL,
+    loans_moved INT NOT NULL,
+    FOREIGN KEY (primary_id) REFERENCES members (member_id),
+    FOREIGN KEY (merged_by) REFERENCES members (member_id) ON DELETE SET NULL
+);
+
+CREATE INDEX member_merges_primary_id_idx ON member_merges (primary_id);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE loan_statistics;
+DELETE FROM loans WHERE member_id IS NULL;
+ALTER TABLE loans ALTER COLUMN member_id SET NOT NULL;
+ALTER TABLE members DROP COLUMN keep_history;
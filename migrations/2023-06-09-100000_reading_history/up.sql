@@ -0,0 +1,20 @@
+-- Your SQL goes here
+
+-- members who opt in keep their returned loans linked to their account
+ALTER TABLE members ADD COLUMN keep_history BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- returned loans of members who did not opt in are detached from the member
+ALTER TABLE loans ALTER COLUMN member_id DROP NOT NULL;
+
+-- one row per closed loan, without anything that identifies the borrower
+CREATE TABLE loan_statistics (
+    stat_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    book_id UUID NOT NULL,
+    tier TEXT NOT NULL,
+    loan_date DATE NOT NULL,
+    return_date DATE NOT NULL,
+    status loan_status NOT NULL,
+    FOREIGN KEY (book_id) REFERENCES books (book_id)
+);
+
+CREATE INDEX loan_statistics_book_id_idx ON loan_statistics (book_id);
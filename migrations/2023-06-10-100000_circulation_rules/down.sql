@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE circulation_rules;
+ALTER TABLE books DROP COLUMN branch;
+ALTER TABLE books DROP COLUMN item_type;
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+
+ALTER TABLE books ADD COLUMN item_type TEXT NOT NULL DEFAULT 'book';
+ALTER TABLE books ADD COLUMN branch TEXT NOT NULL DEFAULT 'main';
+
+-- loan periods by member tier, item type and branch; NULL matches anything
+CREATE TABLE circulation_rules (
+    rule_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tier TEXT,
+    item_type TEXT,
+    branch TEXT,
+    loan_days INT NOT NULL CHECK (loan_days > 0),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX circulation_rules_scope_idx ON circulation_rules (
+    COALESCE(tier, ''),
+    COALESCE(item_type, ''),
+    COALESCE(branch, '')
+);
+
+-- the catch-all rule, matching the loan period most clients used so far
+INSERT INTO circulation_rules (loan_days) VALUES (14);
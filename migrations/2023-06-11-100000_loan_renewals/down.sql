@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE circulation_rules DROP COLUMN renewal_overdue_days;
+ALTER TABLE circulation_rules DROP COLUMN max_renewals;
+ALTER TABLE loans DROP COLUMN renewal_count;
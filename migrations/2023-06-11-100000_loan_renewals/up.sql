@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+ALTER TABLE loans ADD COLUMN renewal_count INT NOT NULL DEFAULT 0;
+
+ALTER TABLE circulation_rules ADD COLUMN max_renewals INT NOT NULL DEFAULT 2 CHECK (max_renewals >= 0);
+-- how many days past its due date a loan may still be renewed
+ALTER TABLE circulation_rules ADD COLUMN renewal_overdue_days INT NOT NULL DEFAULT 0 CHECK (renewal_overdue_days >= 0);
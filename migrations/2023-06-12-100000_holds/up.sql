@@ -0,0 +1,24 @@
+-- Your SQL goes here
+
+CREATE TYPE hold_status AS ENUM ('waiting', 'ready', 'fulfilled', 'cancelled', 'expired');
+
+-- a FIFO queue of members waiting for a book, oldest hold first
+CREATE TABLE holds (
+    hold_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    book_id UUID NOT NULL,
+    member_id UUID NOT NULL,
+    status hold_status NOT NULL DEFAULT 'waiting',
+    placed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- set once the book is on the hold shelf for the member
+    ready_at TIMESTAMP,
+    pickup_expires_on DATE,
+    closed_at TIMESTAMP,
+    FOREIGN KEY (book_id) REFERENCES books (book_id),
+    FOREIGN KEY (member_id) REFERENCES members (member_id)
+);
+
+CREATE INDEX holds_book_id_idx ON holds (book_id, placed_at);
+CREATE INDEX holds_member_id_idx ON holds (member_id);
+-- a member can only queue once for the same book
+CREATE UNIQUE INDEX holds_active_idx ON holds (book_id, member_id)
+    WHERE status IN ('waiting', 'ready');
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE fines;
+DROP TYPE fine_entry_type;
+ALTER TABLE circulation_rules DROP COLUMN max_fine_cents;
+ALTER TABLE circulation_rules DROP COLUMN fine_grace_days;
+ALTER TABLE circulation_rules DROP COLUMN daily_fine_cents;
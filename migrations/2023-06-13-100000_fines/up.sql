@@ -0,0 +1,27 @@
+-- Your SQL goes here
+
+ALTER TABLE circulation_rules ADD COLUMN daily_fine_cents INT NOT NULL DEFAULT 25 CHECK (daily_fine_cents >= 0);
+-- days past the due date that are not charged
+ALTER TABLE circulation_rules ADD COLUMN fine_grace_days INT NOT NULL DEFAULT 0 CHECK (fine_grace_days >= 0);
+ALTER TABLE circulation_rules ADD COLUMN max_fine_cents INT NOT NULL DEFAULT 1000 CHECK (max_fine_cents >= 0);
+
+CREATE TYPE fine_entry_type AS ENUM ('charge', 'payment', 'waiver');
+
+-- charges add to the balance of a member, payments and waivers settle it
+CREATE TABLE fines (
+    entry_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    member_id UUID NOT NULL,
+    loan_id UUID,
+    entry_type fine_entry_type NOT NULL,
+    amount_cents INT NOT NULL CHECK (amount_cents >= 0),
+    note TEXT,
+    created_by UUID,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (member_id) REFERENCES members (member_id),
+    FOREIGN KEY (loan_id) REFERENCES loans (loan_id),
+    FOREIGN KEY (created_by) REFERENCES members (member_id) ON DELETE SET NULL
+);
+
+CREATE INDEX fines_member_id_idx ON fines (member_id);
+-- late fees accrue on a single charge per loan
+CREATE UNIQUE INDEX fines_loan_charge_idx ON fines (loan_id) WHERE entry_type = 'charge';
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+
+-- every attempt to deliver a due date or overdue notice
+CREATE TABLE notices (
+    notice_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    loan_id UUID NOT NULL,
+    member_id UUID NOT NULL,
+    -- 'due_soon' or 'overdue'
+    kind TEXT NOT NULL,
+    -- the due date the notice is about, so a renewed loan is noticed again
+    due_date DATE NOT NULL,
+    recipient TEXT NOT NULL,
+    sent BOOLEAN NOT NULL,
+    error TEXT,
+    attempted_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (loan_id) REFERENCES loans (loan_id),
+    FOREIGN KEY (member_id) REFERENCES members (member_id)
+);
+
+CREATE INDEX notices_member_id_idx ON notices (member_id);
+-- a notice is delivered at most once; failed attempts may be retried
+CREATE UNIQUE INDEX notices_sent_idx ON notices (loan_id, kind, due_date) WHERE sent;
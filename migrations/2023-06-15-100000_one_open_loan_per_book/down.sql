@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX loans_open_book_idx;
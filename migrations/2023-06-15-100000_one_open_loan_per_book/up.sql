@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- a book can only be out on one loan at a time
+CREATE UNIQUE INDEX loans_open_book_idx ON loans (book_id) WHERE status IN ('open', 'overdue');
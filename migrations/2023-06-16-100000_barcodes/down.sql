@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE members DROP COLUMN card_number;
+ALTER TABLE books DROP COLUMN barcode;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- what the desk scans: the label on the book and the library card
+ALTER TABLE books ADD COLUMN barcode TEXT UNIQUE;
+ALTER TABLE members ADD COLUMN card_number TEXT UNIQUE;
@@ -0,0 +1,22 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE members DROP COLUMN claims_returned_count;
+ALTER TABLE books DROP COLUMN replacement_cost_cents;
+
+DELETE FROM fines WHERE entry_type IN ('replacement', 'refund');
+DROP INDEX fines_loan_charge_idx;
+ALTER TYPE fine_entry_type RENAME TO fine_entry_type_old;
+CREATE TYPE fine_entry_type AS ENUM ('charge', 'payment', 'waiver');
+ALTER TABLE fines ALTER COLUMN entry_type TYPE fine_entry_type USING entry_type::text::fine_entry_type;
+DROP TYPE fine_entry_type_old;
+CREATE UNIQUE INDEX fines_loan_charge_idx ON fines (loan_id) WHERE entry_type = 'charge';
+
+UPDATE loans SET status = 'returned', return_date = COALESCE(return_date, CURRENT_DATE)
+    WHERE status IN ('lost', 'claims_returned');
+DROP INDEX loans_open_book_idx;
+ALTER TYPE loan_status RENAME TO loan_status_old;
+CREATE TYPE loan_status AS ENUM ('open', 'returned', 'overdue');
+ALTER TABLE loans ALTER COLUMN status TYPE loan_status USING status::text::loan_status;
+ALTER TABLE loan_statistics ALTER COLUMN status TYPE loan_status USING status::text::loan_status;
+DROP TYPE loan_status_old;
+CREATE UNIQUE INDEX loans_open_book_idx ON loans (book_id) WHERE status IN ('open', 'overdue');
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+ALTER TYPE loan_status ADD VALUE 'lost';
+-- the member says the book came back but it was never checked in
+ALTER TYPE loan_status ADD VALUE 'claims_returned';
+
+-- a replacement is billed like a charge, a refund settles like a payment
+ALTER TYPE fine_entry_type ADD VALUE 'replacement';
+ALTER TYPE fine_entry_type ADD VALUE 'refund';
+
+-- what a lost copy is billed at, NULL for the library default
+ALTER TABLE books ADD COLUMN replacement_cost_cents INT CHECK (replacement_cost_cents >= 0);
+-- kept after the book turns up, for staff to spot a pattern
+ALTER TABLE members ADD COLUMN claims_returned_count INT NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE ill_messages;
+DROP TABLE ill_requests;
+DROP TYPE ill_status;
+DROP TYPE ill_role;
@@ -0,0 +1,61 @@
+-- Your SQL goes here
+
+-- whether we borrow from a partner for one of our members, or lend to one
+CREATE TYPE ill_role AS ENUM ('borrowing', 'lending');
+-- the ISO 18626 request states, plus 'submitted' and 'requested' for
+-- borrowing requests not yet sent to and not yet answered by a partner
+CREATE TYPE ill_status AS ENUM (
+    'submitted',
+    'requested',
+    'request_received',
+    'expect_to_supply',
+    'will_supply',
+    'loaned',
+    'overdue',
+    'recalled',
+    'retry_possible',
+    'unfilled',
+    'loan_completed',
+    'cancelled'
+);
+
+CREATE TABLE ill_requests (
+    ill_request_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    role ill_role NOT NULL,
+    -- agencies as ISO 18626 "type:value", e.g. ISIL:DK-710100
+    requesting_agency TEXT NOT NULL,
+    supplying_agency TEXT,
+    -- the id the requesting agency gave the request, ours when borrowing
+    requesting_request_id TEXT NOT NULL,
+    member_id UUID,
+    -- our copy when lending, the copy received from the partner when borrowing
+    book_id UUID,
+    title TEXT NOT NULL,
+    author TEXT,
+    isbn TEXT,
+    status ill_status NOT NULL,
+    -- when the partner wants a borrowed copy back, or we want a lent one back
+    due_date DATE,
+    note TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (requesting_agency, requesting_request_id),
+    FOREIGN KEY (member_id) REFERENCES members (member_id),
+    FOREIGN KEY (book_id) REFERENCES books (book_id)
+);
+
+CREATE INDEX ill_requests_status_idx ON ill_requests (status);
+CREATE INDEX ill_requests_book_id_idx ON ill_requests (book_id);
+
+-- every ISO 18626 message exchanged; outbound ones wait here for the partner
+-- integration to deliver them
+CREATE TABLE ill_messages (
+    message_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    ill_request_id UUID NOT NULL,
+    inbound BOOLEAN NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (ill_request_id) REFERENCES ill_requests (ill_request_id) ON DELETE CASCADE
+);
+
+CREATE INDEX ill_messages_request_idx ON ill_messages (ill_request_id);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+-- days a branch is closed, either every week or once; NULL branch closes all
+-- of them
+CREATE TABLE closures (
+    closure_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    branch TEXT,
+    -- ISO weekday, 1 is Monday
+    weekday INT CHECK (weekday BETWEEN 1 AND 7),
+    closed_on DATE,
+    reason TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK ((weekday IS NULL) <> (closed_on IS NULL))
+);
+
+CREATE INDEX closures_branch_idx ON closures (branch);
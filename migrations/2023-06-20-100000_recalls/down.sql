@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE circulation_rules DROP COLUMN recall_daily_fine_cents;
+ALTER TABLE loans DROP COLUMN recall_hold_id;
+ALTER TABLE loans DROP COLUMN recalled_at;
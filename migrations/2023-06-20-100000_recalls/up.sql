@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+-- a recalled loan is wanted back early by the member holding recall_hold_id
+ALTER TABLE loans ADD COLUMN recalled_at TIMESTAMP;
+ALTER TABLE loans ADD COLUMN recall_hold_id UUID REFERENCES holds (hold_id) ON DELETE SET NULL;
+
+-- late fees run faster once a loan was recalled
+ALTER TABLE circulation_rules ADD COLUMN recall_daily_fine_cents INT NOT NULL DEFAULT 100
+    CHECK (recall_daily_fine_cents >= 0);
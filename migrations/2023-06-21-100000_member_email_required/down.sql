@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE members DROP CONSTRAINT members_email_required;
+ALTER TABLE members DROP COLUMN dependent;
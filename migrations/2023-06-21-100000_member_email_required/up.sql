@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+-- only dependents, reached through their guardian, may go without an email;
+-- staff accounts such as the bootstrap administrator and anonymized members
+-- have none either
+ALTER TABLE members ADD COLUMN dependent BOOLEAN NOT NULL DEFAULT false;
+UPDATE members SET dependent = true
+    WHERE member_id IN (SELECT dependent_id FROM member_relationships);
+ALTER TABLE members ADD CONSTRAINT members_email_required CHECK (
+    email IS NOT NULL OR dependent OR role <> 'patron' OR anonymized_at IS NOT NULL
+);
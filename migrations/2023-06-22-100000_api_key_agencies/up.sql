@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- the partner library a key exchanging ISO 18626 messages belongs to, as
+-- type:value, e.g. ISIL:DK-710100
+ALTER TABLE api_keys ADD COLUMN agency TEXT;
@@ -0,0 +1,50 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+};
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+
+use super::models::{get_api_keys, issue_api_key, revoke_api_key, ApiKeyRequest};
+
+#[post("/admin/api-keys")]
+async fn create_api_key(principal: Principal, payload: web::Json<ApiKeyRequest>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageApiKeys) {
+        return denied;
+    }
+    // a key can do no more than whoever issued it
+    for &scope in &payload.scopes {
+        if let Err(denied) = principal.require(scope) {
+            return denied;
+        }
+    }
+    let mut conn = establish_connection();
+    match issue_api_key(payload.into_inner(), principal.member_id, &mut conn) {
+        Ok(issued) => HttpResponse::Ok().json(issued),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[get("/admin/api-keys")]
+async fn fetch_api_keys(principal: Principal) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageApiKeys) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_api_keys(&mut conn) {
+        Ok(keys) => HttpResponse::Ok().json(keys),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[delete("/admin/api-keys/{key_id}")]
+async fn remove_api_key(principal: Principal, key_id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageApiKeys) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match revoke_api_key(*key_id, &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
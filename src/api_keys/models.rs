@@ -0,0 +1,183 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::{Insertable, Queryable},
+    BoolExpressionMethods, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl,
+    RunQueryDsl, Selectable, SelectableHelper,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{auth::permissions::Permission, ill::iso18626::AgencyId, schema::api_keys};
+
+/// Marks API keys so the authentication middleware can tell them apart from
+/// session tokens and JWTs.
+pub const API_KEY_PREFIX: &str = "lsk_";
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyRequest {
+    pub name: String,
+    pub scopes: Vec<Permission>,
+    pub expires_at: Option<NaiveDateTime>,
+    /// The partner library the key is for, needed to exchange interlibrary
+    /// loan messages.
+    #[serde(default)]
+    pub agency: Option<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = api_keys)]
+pub struct NewApiKey {
+    pub name: String,
+    pub prefix: String,
+    pub key_hash: String,
+    pub scopes: Vec<Option<String>>,
+    pub created_by: Option<Uuid>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub agency: Option<String>,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = api_keys)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ApiKey {
+    pub key_id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<Option<String>>,
+    pub created_by: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub agency: Option<String>,
+}
+
+impl ApiKey {
+    /// The scopes of the key, skipping any that are no longer known.
+    pub fn permissions(&self) -> Vec<Permission> {
+        self.scopes
+            .iter()
+            .flatten()
+            .filter_map(|scope| scope.parse().ok())
+            .collect()
+    }
+}
+
+/// Returned once when a key is issued; only its hash is kept.
+#[derive(Debug, Serialize)]
+pub struct IssuedApiKey {
+    pub key_id: Uuid,
+    pub key: String,
+    pub prefix: String,
+    pub scopes: Vec<Permission>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+fn hash_key(key: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(key.as_bytes()))
+}
+
+pub fn issue_api_key(
+    request: ApiKeyRequest,
+    created_by: Option<Uuid>,
+    conn: &mut PgConnection,
+) -> Result<IssuedApiKey> {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let key = format!("{API_KEY_PREFIX}{}", URL_SAFE_NO_PAD.encode(bytes));
+    let prefix = key[..DISPLAY_PREFIX_LEN].to_string();
+
+    let key_id = diesel::insert_into(api_keys::table)
+        .values(&NewApiKey {
+            name: request.name,
+            prefix: prefix.clone(),
+            key_hash: hash_key(&key),
+            scopes: request
+                .scopes
+                .iter()
+                .map(|p| Some(p.as_str().to_string()))
+                .collect(),
+            created_by,
+            expires_at: request.expires_at,
+            agency: request
+                .agency
+                .as_deref()
+                .map(|agency| AgencyId::parse(agency).to_string()),
+        })
+        .returning(api_keys::key_id)
+        .get_result(conn)?;
+
+    Ok(IssuedApiKey {
+        key_id,
+        key,
+        prefix,
+        scopes: request.scopes,
+        expires_at: request.expires_at,
+    })
+}
+
+pub fn get_api_keys(conn: &mut PgConnection) -> Result<Vec<ApiKey>> {
+    Ok(api_keys::table
+        .order(api_keys::created_at.desc())
+        .select(ApiKey::as_select())
+        .load(conn)?)
+}
+
+/// The keys a staff member issued, newest first.
+pub fn get_issued_api_keys(member_id: Uuid, conn: &mut PgConnection) -> Result<Vec<ApiKey>> {
+    Ok(api_keys::table
+        .filter(api_keys::created_by.eq(member_id))
+        .order(api_keys::created_at.desc())
+        .select(ApiKey::as_select())
+        .load(conn)?)
+}
+
+pub fn revoke_api_key(key_id: Uuid, conn: &mut PgConnection) -> Result<bool> {
+    let num_updated = diesel::update(
+        api_keys::table
+            .filter(api_keys::key_id.eq(key_id))
+            .filter(api_keys::revoked_at.is_null()),
+    )
+    .set(api_keys::revoked_at.eq(chrono::Utc::now().naive_utc()))
+    .execute(conn)?;
+    Ok(num_updated > 0)
+}
+
+/// Revokes every live key a staff member issued, returning how many there were.
+pub fn revoke_issued_api_keys(member_id: Uuid, conn: &mut PgConnection) -> Result<usize> {
+    Ok(diesel::update(
+        api_keys::table
+            .filter(api_keys::created_by.eq(member_id))
+            .filter(api_keys::revoked_at.is_null()),
+    )
+    .set(api_keys::revoked_at.eq(chrono::Utc::now().naive_utc()))
+    .execute(conn)?)
+}
+
+/// Looks up a live key and records that it was used.
+pub fn find_api_key(key: &str, conn: &mut PgConnection) -> Result<Option<ApiKey>> {
+    let now = chrono::Utc::now().naive_utc();
+    let found = api_keys::table
+        .filter(api_keys::key_hash.eq(hash_key(key)))
+        .filter(api_keys::revoked_at.is_null())
+        .filter(
+            api_keys::expires_at
+                .is_null()
+                .or(api_keys::expires_at.gt(now)),
+        )
+        .select(ApiKey::as_select())
+        .first(conn)
+        .optional()?;
+
+    if let Some(found) = &found {
+        diesel::update(api_keys::table.filter(api_keys::key_id.eq(found.key_id)))
+            .set(api_keys::last_used_at.eq(now))
+            .execute(conn)?;
+    }
+    Ok(found)
+}
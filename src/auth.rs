@@ -0,0 +1,5 @@
+pub mod extractors;
+pub mod handlers;
+pub mod middleware;
+pub mod models;
+pub mod permissions;
@@ -0,0 +1,81 @@
+use std::future::{ready, Ready};
+
+use actix_web::{
+    dev::Payload, error::ErrorUnauthorized, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::members::models::Role;
+
+use super::permissions::Permission;
+
+pub const SESSION_COOKIE: &str = "libstack_session";
+
+/// What a [`Principal`] is allowed to do.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Grant {
+    /// A signed-in member, allowed whatever their role allows.
+    Role(Role),
+    /// A machine client, allowed exactly the scopes of its API key or token.
+    Scopes(Vec<Permission>),
+}
+
+/// Who a request was made by, as established by
+/// [`Authenticate`](super::middleware::Authenticate).
+///
+/// Taking a `Principal` argument makes a handler require credentials: a member
+/// session, an API key or a signed JWT.
+#[derive(Debug, Clone, Serialize)]
+pub struct Principal {
+    pub subject: String,
+    pub member_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    pub grant: Grant,
+    /// The partner library an API key was issued to.
+    pub agency: Option<String>,
+}
+
+impl Principal {
+    pub fn allows(&self, permission: Permission) -> bool {
+        match &self.grant {
+            Grant::Role(role) => role.allows(permission),
+            Grant::Scopes(scopes) => scopes.contains(&permission),
+        }
+    }
+
+    /// Refuses with `403 Forbidden` unless the caller holds `permission`.
+    pub fn require(&self, permission: Permission) -> Result<(), HttpResponse> {
+        if self.allows(permission) {
+            Ok(())
+        } else {
+            Err(HttpResponse::Forbidden().json(format!("missing permission {permission:?}")))
+        }
+    }
+
+    /// Like [`Principal::require`], but also lets signed-in members act on
+    /// their own record when `permission` is a self-service one.
+    pub fn require_for(&self, member_id: Uuid, permission: Permission) -> Result<(), HttpResponse> {
+        let own_record = matches!(self.grant, Grant::Role(_)) && self.member_id == Some(member_id);
+        if own_record && permission.self_service() {
+            Ok(())
+        } else {
+            self.require(permission)
+        }
+    }
+}
+
+impl FromRequest for Principal {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Principal>()
+                .cloned()
+                .ok_or_else(|| ErrorUnauthorized("missing credentials")),
+        )
+    }
+}
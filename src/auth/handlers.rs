@@ -0,0 +1,103 @@
+use crate::{db::establish_connection, members::models::get_member};
+use actix_web::{
+    cookie::{time::Duration as CookieDuration, Cookie, SameSite},
+    delete, get, post, put, web, HttpResponse, Responder,
+};
+
+use super::{
+    extractors::{Principal, SESSION_COOKIE},
+    models::{
+        login as open_session, revoke_member_sessions, revoke_session, set_credentials, AuthConfig,
+        CredentialsRequest,
+    },
+    permissions::Permission,
+};
+
+#[post("/auth/login")]
+async fn login(
+    config: web::Data<AuthConfig>,
+    payload: web::Json<CredentialsRequest>,
+) -> impl Responder {
+    let mut conn = establish_connection();
+    match open_session(
+        &payload.username,
+        &payload.password,
+        config.session_ttl,
+        &mut conn,
+    ) {
+        Ok(Some(session)) => {
+            let cookie = Cookie::build(SESSION_COOKIE, session.token.clone())
+                .path("/")
+                .http_only(true)
+                .same_site(SameSite::Strict)
+                .max_age(CookieDuration::seconds(config.session_ttl.num_seconds()))
+                .finish();
+            HttpResponse::Ok().cookie(cookie).json(session)
+        }
+        Ok(None) => HttpResponse::Unauthorized().json("invalid username or password"),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[post("/auth/logout")]
+async fn logout(principal: Principal) -> impl Responder {
+    let Some(session_id) = principal.session_id else {
+        return HttpResponse::BadRequest().json("not signed in with a session");
+    };
+    let mut conn = establish_connection();
+    match revoke_session(session_id, &mut conn) {
+        Ok(_) => {
+            let mut cookie = Cookie::named(SESSION_COOKIE);
+            cookie.set_path("/");
+            cookie.make_removal();
+            HttpResponse::Ok().cookie(cookie).finish()
+        }
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[get("/auth/session")]
+async fn current_session(principal: Principal) -> impl Responder {
+    let Some(member_id) = principal.member_id else {
+        return HttpResponse::Ok().json(principal);
+    };
+    let mut conn = establish_connection();
+    match get_member(member_id, &mut conn) {
+        Ok(Some(member)) => HttpResponse::Ok().json(member),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[put("/members/{member_id}/credentials")]
+async fn change_credentials(
+    principal: Principal,
+    id: web::Path<uuid::Uuid>,
+    payload: web::Json<CredentialsRequest>,
+) -> impl Responder {
+    if let Err(denied) = principal.require_for(*id, Permission::ManageCredentials) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_member(*id, &mut conn) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(format!("member {id} not found")),
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+    match set_credentials(*id, &payload.username, &payload.password, &mut conn) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => HttpResponse::BadRequest().json(format!("{e}")),
+    }
+}
+
+#[delete("/members/{member_id}/sessions")]
+async fn revoke_sessions(principal: Principal, id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require_for(*id, Permission::ManageCredentials) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match revoke_member_sessions(*id, &mut conn) {
+        Ok(revoked) => HttpResponse::Ok().json(revoked),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    future::{ready, Future, Ready},
+    pin::Pin,
+    sync::Arc,
+};
+
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::{ErrorInternalServerError, ErrorUnauthorized},
+    http::header,
+    Error, HttpMessage, HttpRequest,
+};
+
+use crate::{
+    api_keys::models::{find_api_key, API_KEY_PREFIX},
+    db::establish_connection,
+    members::models::get_member,
+};
+
+use super::{
+    extractors::{Grant, Principal, SESSION_COOKIE},
+    models::{find_session, JwtConfig},
+};
+
+/// Resolves the credentials of a request into a [`Principal`] stored in the
+/// request extensions.
+///
+/// `Authorization: Bearer` accepts API keys (`lsk_...`), JWTs signed with the
+/// configured key, and session tokens; browsers may send the session cookie
+/// instead. Requests without credentials pass through and are refused by the
+/// `Principal` extractor where needed, while bad credentials are refused here.
+#[derive(Clone)]
+pub struct Authenticate {
+    jwt: Option<Arc<JwtConfig>>,
+}
+
+impl Authenticate {
+    pub fn new(jwt: Option<JwtConfig>) -> Self {
+        Self {
+            jwt: jwt.map(Arc::new),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Authenticate
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthenticateMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticateMiddleware {
+            service,
+            jwt: self.jwt.clone(),
+        }))
+    }
+}
+
+pub struct AuthenticateMiddleware<S> {
+    service: S,
+    jwt: Option<Arc<JwtConfig>>,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+impl<S, B> Service<ServiceRequest> for AuthenticateMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        match authenticate(req.request(), self.jwt.as_deref()) {
+            Ok(Some(principal)) => {
+                req.extensions_mut().insert(principal);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let response = req.error_response(e).map_into_right_body();
+                return Box::pin(async { Ok(response) });
+            }
+        }
+
+        let fut = self.service.call(req);
+        Box::pin(async move { fut.await.map(ServiceResponse::map_into_left_body) })
+    }
+}
+
+fn credentials(req: &HttpRequest) -> Option<String> {
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+
+    bearer.or_else(|| req.cookie(SESSION_COOKIE).map(|c| c.value().to_string()))
+}
+
+fn authenticate(req: &HttpRequest, jwt: Option<&JwtConfig>) -> Result<Option<Principal>, Error> {
+    let Some(token) = credentials(req) else {
+        return Ok(None);
+    };
+
+    if token.starts_with(API_KEY_PREFIX) {
+        let mut conn = establish_connection();
+        return match find_api_key(&token, &mut conn) {
+            Ok(Some(key)) => Ok(Some(Principal {
+                subject: format!("api_key:{}", key.key_id),
+                member_id: None,
+                session_id: None,
+                grant: Grant::Scopes(key.permissions()),
+                agency: key.agency,
+            })),
+            Ok(None) => Err(ErrorUnauthorized("invalid or revoked API key")),
+            Err(e) => Err(ErrorInternalServerError(e.to_string())),
+        };
+    }
+
+    if token.split('.').count() == 3 {
+        let jwt = jwt.ok_or_else(|| ErrorUnauthorized("bearer tokens are not accepted"))?;
+        let claims = jwt
+            .decode(&token)
+            .map_err(|e| ErrorUnauthorized(format!("invalid bearer token: {e}")))?;
+        return Ok(Some(Principal {
+            member_id: claims.sub.parse().ok(),
+            session_id: None,
+            grant: Grant::Scopes(claims.permissions()),
+            agency: None,
+            subject: claims.sub,
+        }));
+    }
+
+    let mut conn = establish_connection();
+    let session = match find_session(&token, &mut conn) {
+        Ok(Some(session)) => session,
+        Ok(None) => return Err(ErrorUnauthorized("invalid or expired session")),
+        Err(e) => return Err(ErrorInternalServerError(e.to_string())),
+    };
+    match get_member(session.member_id, &mut conn) {
+        Ok(Some(member)) => Ok(Some(Principal {
+            subject: format!("member:{}", member.member_id),
+            member_id: Some(member.member_id),
+            session_id: Some(session.session_id),
+            grant: Grant::Role(member.role),
+            agency: None,
+        })),
+        Ok(None) => Err(ErrorUnauthorized("invalid or expired session")),
+        Err(e) => Err(ErrorInternalServerError(e.to_string())),
+    }
+}
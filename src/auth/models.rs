@@ -0,0 +1,354 @@
+use anyhow::Result;
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::{Insertable, Queryable},
+    upsert::excluded,
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+    Selectable, SelectableHelper,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    auth::permissions::Permission,
+    errors::LibError,
+    members::models::{NewMember, Role, DEFAULT_TIER},
+    schema::{credentials, members, sessions},
+};
+
+const DEFAULT_SESSION_TTL_MINUTES: i64 = 12 * 60;
+const MIN_PASSWORD_LENGTH: usize = 8;
+/// Checked against when a username is unknown, so a failed login takes as
+/// long whether or not the username exists. Made by [`hash_password`], so it
+/// costs the same to check; the login fails whatever the result.
+const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$KwRbSmdmYAluXuRAxcqwpQ$fDCBGOdqv+UT5rlRQ+NsWh97BLjX/gaAL8l8hOpbbok";
+
+/// Authentication settings shared with the handlers through `app_data`.
+#[derive(Debug, Clone)]
+pub struct AuthConfig {
+    pub session_ttl: chrono::Duration,
+}
+
+impl AuthConfig {
+    /// Reads `SESSION_TTL_MINUTES`, defaulting to twelve hours.
+    pub fn from_env() -> Self {
+        let minutes = std::env::var("SESSION_TTL_MINUTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_SESSION_TTL_MINUTES);
+        Self {
+            session_ttl: chrono::Duration::minutes(minutes),
+        }
+    }
+}
+
+/// Verification settings for JWT bearer tokens issued to machine clients.
+///
+/// Tokens are signed either with a shared `JWT_HS256_SECRET` or with an Ed25519
+/// key whose public half is given as PEM in `JWT_EDDSA_PUBLIC_KEY`. Optional
+/// `JWT_ISSUER` and `JWT_AUDIENCE` are checked against the `iss` and `aud`
+/// claims.
+#[derive(Clone)]
+pub struct JwtConfig {
+    key: DecodingKey,
+    validation: Validation,
+}
+
+/// The claims read from a JWT, whose `exp` is checked while decoding. `scope`
+/// lists permissions separated by spaces.
+#[derive(Debug, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    #[serde(default)]
+    pub scope: String,
+}
+
+impl Claims {
+    pub fn permissions(&self) -> Vec<Permission> {
+        self.scope
+            .split_whitespace()
+            .filter_map(|scope| scope.parse().ok())
+            .collect()
+    }
+}
+
+impl JwtConfig {
+    /// Returns `None` when no signing key is configured, in which case JWTs
+    /// are refused.
+    pub fn from_env() -> Result<Option<Self>> {
+        let (key, algorithm) = if let Ok(secret) = std::env::var("JWT_HS256_SECRET") {
+            (
+                DecodingKey::from_secret(secret.as_bytes()),
+                Algorithm::HS256,
+            )
+        } else if let Ok(pem) = std::env::var("JWT_EDDSA_PUBLIC_KEY") {
+            (DecodingKey::from_ed_pem(pem.as_bytes())?, Algorithm::EdDSA)
+        } else {
+            return Ok(None);
+        };
+
+        let mut validation = Validation::new(algorithm);
+        validation.validate_aud = false;
+        if let Ok(audience) = std::env::var("JWT_AUDIENCE") {
+            validation.set_audience(&[audience]);
+            validation.validate_aud = true;
+        }
+        if let Ok(issuer) = std::env::var("JWT_ISSUER") {
+            validation.set_issuer(&[issuer]);
+        }
+
+        Ok(Some(Self { key, validation }))
+    }
+
+    pub fn decode(&self, token: &str) -> Result<Claims> {
+        Ok(jsonwebtoken::decode::<Claims>(token, &self.key, &self.validation)?.claims)
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = credentials)]
+pub struct NewCredentials {
+    pub member_id: Uuid,
+    pub username: String,
+    pub password_hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CredentialsRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = sessions)]
+pub struct NewSession {
+    pub member_id: Uuid,
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = sessions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Session {
+    pub session_id: Uuid,
+    pub member_id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+/// Returned once on login; only the hash of `token` is kept server-side.
+#[derive(Debug, Serialize)]
+pub struct SessionToken {
+    pub session_id: Uuid,
+    pub member_id: Uuid,
+    pub token: String,
+    pub expires_at: NaiveDateTime,
+}
+
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| LibError::Auth(e.to_string()))?
+        .to_string())
+}
+
+fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .and_then(|hash| Argon2::default().verify_password(password.as_bytes(), &hash))
+        .is_ok()
+}
+
+fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Creates or replaces the login of a member. Changing the password signs the
+/// member out everywhere.
+pub fn set_credentials(
+    member_id: Uuid,
+    username: &str,
+    password: &str,
+    conn: &mut PgConnection,
+) -> Result<()> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(LibError::Auth(format!(
+            "password must be at least {MIN_PASSWORD_LENGTH} characters"
+        ))
+        .into());
+    }
+    let new_credentials = NewCredentials {
+        member_id,
+        username: username.trim().to_lowercase(),
+        password_hash: hash_password(password)?,
+    };
+
+    conn.transaction(|conn| {
+        diesel::insert_into(credentials::table)
+            .values(&new_credentials)
+            .on_conflict(credentials::member_id)
+            .do_update()
+            .set((
+                credentials::username.eq(excluded(credentials::username)),
+                credentials::password_hash.eq(excluded(credentials::password_hash)),
+            ))
+            .execute(conn)?;
+        revoke_member_sessions(member_id, conn)?;
+        Ok(())
+    })
+}
+
+/// Removes the login of a member along with all of their sessions.
+pub fn delete_credentials(member_id: Uuid, conn: &mut PgConnection) -> Result<()> {
+    diesel::delete(sessions::table.filter(sessions::member_id.eq(member_id))).execute(conn)?;
+    diesel::delete(credentials::table.filter(credentials::member_id.eq(member_id)))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Checks a username and password and opens a new session on success.
+pub fn login(
+    username: &str,
+    password: &str,
+    ttl: chrono::Duration,
+    conn: &mut PgConnection,
+) -> Result<Option<SessionToken>> {
+    let found: Option<(Uuid, String)> = credentials::table
+        .filter(credentials::username.eq(username.trim().to_lowercase()))
+        .select((credentials::member_id, credentials::password_hash))
+        .first(conn)
+        .optional()?;
+    let Some((member_id, hash)) = found else {
+        verify_password(password, DUMMY_PASSWORD_HASH);
+        return Ok(None);
+    };
+    if !verify_password(password, &hash) {
+        return Ok(None);
+    }
+
+    let token = generate_token();
+    let expires_at = chrono::Utc::now().naive_utc() + ttl;
+    let session_id = diesel::insert_into(sessions::table)
+        .values(&NewSession {
+            member_id,
+            token_hash: hash_token(&token),
+            expires_at,
+        })
+        .returning(sessions::session_id)
+        .get_result(conn)?;
+
+    Ok(Some(SessionToken {
+        session_id,
+        member_id,
+        token,
+        expires_at,
+    }))
+}
+
+pub fn get_username(member_id: Uuid, conn: &mut PgConnection) -> Result<Option<String>> {
+    Ok(credentials::table
+        .find(member_id)
+        .select(credentials::username)
+        .first(conn)
+        .optional()?)
+}
+
+/// Every session of a member, live or not, newest first.
+pub fn get_member_sessions(member_id: Uuid, conn: &mut PgConnection) -> Result<Vec<Session>> {
+    Ok(sessions::table
+        .filter(sessions::member_id.eq(member_id))
+        .order(sessions::created_at.desc())
+        .select(Session::as_select())
+        .load(conn)?)
+}
+
+/// Looks up the live session a bearer token belongs to.
+pub fn find_session(token: &str, conn: &mut PgConnection) -> Result<Option<Session>> {
+    Ok(sessions::table
+        .filter(sessions::token_hash.eq(hash_token(token)))
+        .filter(sessions::revoked_at.is_null())
+        .filter(sessions::expires_at.gt(chrono::Utc::now().naive_utc()))
+        .select(Session::as_select())
+        .first(conn)
+        .optional()?)
+}
+
+pub fn revoke_session(session_id: Uuid, conn: &mut PgConnection) -> Result<bool> {
+    let num_updated = diesel::update(
+        sessions::table
+            .filter(sessions::session_id.eq(session_id))
+            .filter(sessions::revoked_at.is_null()),
+    )
+    .set(sessions::revoked_at.eq(chrono::Utc::now().naive_utc()))
+    .execute(conn)?;
+    Ok(num_updated > 0)
+}
+
+pub fn revoke_member_sessions(member_id: Uuid, conn: &mut PgConnection) -> Result<usize> {
+    Ok(diesel::update(
+        sessions::table
+            .filter(sessions::member_id.eq(member_id))
+            .filter(sessions::revoked_at.is_null()),
+    )
+    .set(sessions::revoked_at.eq(chrono::Utc::now().naive_utc()))
+    .execute(conn)?)
+}
+
+/// Creates an administrator from `ADMIN_USERNAME` and `ADMIN_PASSWORD` unless a
+/// login with that username already exists, so a fresh install can be managed
+/// through the API at all.
+pub fn bootstrap_admin(conn: &mut PgConnection) -> Result<()> {
+    let (Ok(username), Ok(password)) = (
+        std::env::var("ADMIN_USERNAME"),
+        std::env::var("ADMIN_PASSWORD"),
+    ) else {
+        return Ok(());
+    };
+
+    let existing: i64 = credentials::table
+        .filter(credentials::username.eq(username.trim().to_lowercase()))
+        .count()
+        .get_result(conn)?;
+    if existing > 0 {
+        return Ok(());
+    }
+
+    conn.transaction(|conn| {
+        let member_id = diesel::insert_into(members::table)
+            .values((
+                &NewMember {
+                    name: username.clone(),
+                    email: None,
+                    privilege: true,
+                    borrowed: 0,
+                    household_limit: None,
+                    external_id: None,
+                    tier: DEFAULT_TIER.to_string(),
+                    expires_on: None,
+                    card_number: None,
+                    dependent: false,
+                },
+                members::role.eq(Role::Admin),
+            ))
+            .returning(members::member_id)
+            .get_result(conn)?;
+        set_credentials(member_id, &username, &password, conn)
+    })
+}
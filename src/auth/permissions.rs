@@ -0,0 +1,162 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::members::models::Role;
+
+/// An action guarded by the API, checked against the [`Role`] of the caller or
+/// the scopes of their API key or token.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    ReadBooks,
+    ManageBooks,
+    ReadMembers,
+    ManageMembers,
+    ExportMembers,
+    EraseMembers,
+    ManageRoles,
+    ManageCredentials,
+    ManageApiKeys,
+    ManageHouseholds,
+    ManageBlocks,
+    ManageNotes,
+    ReadLoans,
+    CheckOut,
+    CheckIn,
+    RenewLoans,
+    ManagePrivacy,
+    OverrideDueDate,
+    ManagePolicies,
+    PlaceHolds,
+    ManageHolds,
+    ManageFines,
+    RunMaintenance,
+    RequestIll,
+    ManageIll,
+    /// Exchanging ISO 18626 messages, for the API keys of partner libraries.
+    ExchangeIll,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 26] = [
+        Permission::ReadBooks,
+        Permission::ManageBooks,
+        Permission::ReadMembers,
+        Permission::ManageMembers,
+        Permission::ExportMembers,
+        Permission::EraseMembers,
+        Permission::ManageRoles,
+        Permission::ManageCredentials,
+        Permission::ManageApiKeys,
+        Permission::ManageHouseholds,
+        Permission::ManageBlocks,
+        Permission::ManageNotes,
+        Permission::ReadLoans,
+        Permission::CheckOut,
+        Permission::CheckIn,
+        Permission::RenewLoans,
+        Permission::ManagePrivacy,
+        Permission::OverrideDueDate,
+        Permission::ManagePolicies,
+        Permission::PlaceHolds,
+        Permission::ManageHolds,
+        Permission::ManageFines,
+        Permission::RunMaintenance,
+        Permission::RequestIll,
+        Permission::ManageIll,
+        Permission::ExchangeIll,
+    ];
+
+    /// The name of the permission as used in API key scopes and token claims.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::ReadBooks => "read_books",
+            Permission::ManageBooks => "manage_books",
+            Permission::ReadMembers => "read_members",
+            Permission::ManageMembers => "manage_members",
+            Permission::ExportMembers => "export_members",
+            Permission::EraseMembers => "erase_members",
+            Permission::ManageRoles => "manage_roles",
+            Permission::ManageCredentials => "manage_credentials",
+            Permission::ManageApiKeys => "manage_api_keys",
+            Permission::ManageHouseholds => "manage_households",
+            Permission::ManageBlocks => "manage_blocks",
+            Permission::ManageNotes => "manage_notes",
+            Permission::ReadLoans => "read_loans",
+            Permission::CheckOut => "check_out",
+            Permission::CheckIn => "check_in",
+            Permission::RenewLoans => "renew_loans",
+            Permission::ManagePrivacy => "manage_privacy",
+            Permission::OverrideDueDate => "override_due_date",
+            Permission::ManagePolicies => "manage_policies",
+            Permission::PlaceHolds => "place_holds",
+            Permission::ManageHolds => "manage_holds",
+            Permission::ManageFines => "manage_fines",
+            Permission::RunMaintenance => "run_maintenance",
+            Permission::RequestIll => "request_ill",
+            Permission::ManageIll => "manage_ill",
+            Permission::ExchangeIll => "exchange_ill",
+        }
+    }
+
+    /// Whether a patron holds this permission over their own member record and
+    /// loans, even though their role does not grant it in general.
+    pub fn self_service(self) -> bool {
+        matches!(
+            self,
+            Permission::ReadMembers
+                | Permission::ExportMembers
+                | Permission::ManageCredentials
+                | Permission::ReadLoans
+                | Permission::CheckOut
+                | Permission::RenewLoans
+                | Permission::ManagePrivacy
+                | Permission::PlaceHolds
+                | Permission::RequestIll
+        )
+    }
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Permission::ALL
+            .into_iter()
+            .find(|p| p.as_str() == s)
+            .ok_or_else(|| format!("unknown permission {s}"))
+    }
+}
+
+impl Role {
+    pub fn allows(self, permission: Permission) -> bool {
+        use Permission::*;
+
+        match self {
+            Role::Admin => true,
+            Role::Circulation => matches!(
+                permission,
+                ReadBooks
+                    | ReadMembers
+                    | ManageMembers
+                    | ManageHouseholds
+                    | ManageBlocks
+                    | ManageNotes
+                    | ReadLoans
+                    | CheckOut
+                    | CheckIn
+                    | RenewLoans
+                    | ManagePrivacy
+                    | OverrideDueDate
+                    | PlaceHolds
+                    | ManageHolds
+                    | ManageFines
+                    | RequestIll
+                    | ManageIll
+            ),
+            Role::Cataloger => matches!(permission, ReadBooks | ManageBooks | ReadLoans),
+            Role::Patron => matches!(permission, ReadBooks),
+        }
+    }
+}
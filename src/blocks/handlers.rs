@@ -0,0 +1,87 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+    members::models::get_member,
+};
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+
+use super::models::{
+    add_block as create_block, get_blocks, lift_block, update_block, BlockRequest, BlocksFilter,
+};
+
+#[get("/members/{member_id}/blocks")]
+async fn fetch_blocks(
+    principal: Principal,
+    member_id: web::Path<uuid::Uuid>,
+    filter: web::Query<BlocksFilter>,
+) -> impl Responder {
+    if let Err(denied) = principal.require_for(*member_id, Permission::ReadMembers) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_blocks(*member_id, filter.active, &mut conn) {
+        Ok(blocks) => HttpResponse::Ok().json(blocks),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[post("/members/{member_id}/blocks")]
+async fn add_block(
+    principal: Principal,
+    member_id: web::Path<uuid::Uuid>,
+    payload: web::Json<BlockRequest>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageBlocks) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_member(*member_id, &mut conn) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(format!("member {member_id} not found")),
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+    match create_block(
+        *member_id,
+        payload.into_inner(),
+        principal.member_id,
+        &mut conn,
+    ) {
+        Ok(block_id) => HttpResponse::Ok().json(block_id),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[put("/members/{member_id}/blocks/{block_id}")]
+async fn change_block(
+    principal: Principal,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+    payload: web::Json<BlockRequest>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageBlocks) {
+        return denied;
+    }
+    let (member_id, block_id) = path.into_inner();
+    let mut conn = establish_connection();
+    match update_block(member_id, block_id, payload.into_inner(), &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[delete("/members/{member_id}/blocks/{block_id}")]
+async fn remove_block(
+    principal: Principal,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageBlocks) {
+        return denied;
+    }
+    let (member_id, block_id) = path.into_inner();
+    let mut conn = establish_connection();
+    match lift_block(member_id, block_id, &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
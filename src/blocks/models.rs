@@ -0,0 +1,131 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::{Insertable, Queryable},
+    BoolExpressionMethods, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl, Selectable,
+    SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{errors::LibError, schema::member_blocks};
+
+#[derive(Debug, Deserialize)]
+pub struct BlockRequest {
+    pub block_type: String,
+    pub reason: String,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlocksFilter {
+    /// Only list blocks that currently stop circulation.
+    #[serde(default)]
+    pub active: bool,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = member_blocks)]
+pub struct NewBlock {
+    pub member_id: Uuid,
+    pub block_type: String,
+    pub reason: String,
+    pub created_by: Option<Uuid>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = member_blocks)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MemberBlock {
+    pub block_id: Uuid,
+    pub member_id: Uuid,
+    pub block_type: String,
+    pub reason: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: Option<NaiveDateTime>,
+    pub lifted_at: Option<NaiveDateTime>,
+}
+
+pub fn add_block(
+    member_id: Uuid,
+    request: BlockRequest,
+    created_by: Option<Uuid>,
+    conn: &mut PgConnection,
+) -> Result<Uuid> {
+    Ok(diesel::insert_into(member_blocks::table)
+        .values(&NewBlock {
+            member_id,
+            block_type: request.block_type,
+            reason: request.reason,
+            created_by,
+            expires_at: request.expires_at,
+        })
+        .returning(member_blocks::block_id)
+        .get_result(conn)?)
+}
+
+pub fn get_blocks(
+    member_id: Uuid,
+    active: bool,
+    conn: &mut PgConnection,
+) -> Result<Vec<MemberBlock>> {
+    let now = chrono::Utc::now().naive_utc();
+    let mut query = member_blocks::table
+        .filter(member_blocks::member_id.eq(member_id))
+        .order(member_blocks::created_at.desc())
+        .select(MemberBlock::as_select())
+        .into_boxed();
+    if active {
+        query = query.filter(member_blocks::lifted_at.is_null()).filter(
+            member_blocks::expires_at
+                .is_null()
+                .or(member_blocks::expires_at.gt(now)),
+        );
+    }
+    Ok(query.load(conn)?)
+}
+
+pub fn update_block(
+    member_id: Uuid,
+    block_id: Uuid,
+    request: BlockRequest,
+    conn: &mut PgConnection,
+) -> Result<bool> {
+    let num_updated = diesel::update(
+        member_blocks::table
+            .filter(member_blocks::block_id.eq(block_id))
+            .filter(member_blocks::member_id.eq(member_id)),
+    )
+    .set((
+        member_blocks::block_type.eq(request.block_type),
+        member_blocks::reason.eq(request.reason),
+        member_blocks::expires_at.eq(request.expires_at),
+    ))
+    .execute(conn)?;
+    Ok(num_updated > 0)
+}
+
+/// Lifts a block. The row is kept so the history of the account stays visible.
+pub fn lift_block(member_id: Uuid, block_id: Uuid, conn: &mut PgConnection) -> Result<bool> {
+    let num_updated = diesel::update(
+        member_blocks::table
+            .filter(member_blocks::block_id.eq(block_id))
+            .filter(member_blocks::member_id.eq(member_id))
+            .filter(member_blocks::lifted_at.is_null()),
+    )
+    .set(member_blocks::lifted_at.eq(chrono::Utc::now().naive_utc()))
+    .execute(conn)?;
+    Ok(num_updated > 0)
+}
+
+/// Refuses circulation for a member with active blocks, listing all of them.
+pub fn check_member_blocks(member_id: Uuid, conn: &mut PgConnection) -> Result<()> {
+    let blocks = get_blocks(member_id, true, conn)?;
+    if blocks.is_empty() {
+        Ok(())
+    } else {
+        Err(LibError::MemberBlocked(blocks).into())
+    }
+}
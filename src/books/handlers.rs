@@ -1,27 +1,29 @@
-use crate::{books::models::update_book, db::establish_connection};
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    books::models::update_book,
+    db::establish_connection,
+};
 use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
 
 use super::models::{add_book as create_book, delete_book, get_book, NewBook};
 
 #[post("/books/new")]
-async fn add_book(payload: web::Json<NewBook>) -> impl Responder {
+async fn add_book(principal: Principal, payload: web::Json<NewBook>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageBooks) {
+        return denied;
+    }
     let mut connection = establish_connection();
-    let isbn = &*payload.isbn.clone().unwrap_or("null".to_string());
-    match create_book(
-        &payload.title,
-        &payload.author,
-        payload.publication_year,
-        isbn,
-        payload.availability_status,
-        &mut connection,
-    ) {
+    match create_book(payload.into_inner(), &mut connection) {
         Ok(book_id) => HttpResponse::Ok().json(book_id),
         Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
     }
 }
 
 #[delete("/books/{book_id}")]
-async fn remove_book(book_id: web::Path<uuid::Uuid>) -> impl Responder {
+async fn remove_book(principal: Principal, book_id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageBooks) {
+        return denied;
+    }
     let mut connection = establish_connection();
 
     match delete_book(*book_id, &mut connection) {
@@ -31,7 +33,10 @@ async fn remove_book(book_id: web::Path<uuid::Uuid>) -> impl Responder {
 }
 
 #[get("/books/{book_id}")]
-async fn fetch_book(book_id: web::Path<uuid::Uuid>) -> impl Responder {
+async fn fetch_book(principal: Principal, book_id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ReadBooks) {
+        return denied;
+    }
     let mut conn = establish_connection();
 
     match get_book(*book_id, &mut conn) {
@@ -43,9 +48,13 @@ async fn fetch_book(book_id: web::Path<uuid::Uuid>) -> impl Responder {
 
 #[put("/books/{book_id}")]
 async fn change_book(
+    principal: Principal,
     book_id: web::Path<uuid::Uuid>,
     book_request: web::Json<NewBook>,
 ) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageBooks) {
+        return denied;
+    }
     let mut conn = establish_connection();
     match update_book(*book_id, book_request.into_inner(), &mut conn) {
         Ok(updated) => {
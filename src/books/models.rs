@@ -1,6 +1,5 @@
 use crate::{errors::LibError, schema::books};
 
-use actix_web::error::ErrorInternalServerError;
 use anyhow::Context;
 use anyhow::Result;
 use diesel::{
@@ -20,6 +19,26 @@ pub struct NewBook {
     pub publication_year: i32,
     pub isbn: Option<String>,
     pub availability_status: bool,
+    #[serde(default = "default_item_type")]
+    pub item_type: String,
+    #[serde(default = "default_branch")]
+    pub branch: String,
+    #[serde(default)]
+    pub barcode: Option<String>,
+    /// What a lost copy is billed at, the library default when left out.
+    #[serde(default)]
+    pub replacement_cost_cents: Option<i32>,
+}
+
+pub const DEFAULT_ITEM_TYPE: &str = "book";
+pub const DEFAULT_BRANCH: &str = "main";
+
+fn default_item_type() -> String {
+    DEFAULT_ITEM_TYPE.to_string()
+}
+
+fn default_branch() -> String {
+    DEFAULT_BRANCH.to_string()
 }
 
 #[derive(Debug, Queryable, Selectable, AsChangeset, Serialize, Deserialize)]
@@ -32,24 +51,13 @@ pub struct Book {
     pub publication_year: i32,
     pub isbn: Option<String>,
     pub availability_status: bool,
+    pub item_type: String,
+    pub branch: String,
+    pub barcode: Option<String>,
+    pub replacement_cost_cents: Option<i32>,
 }
 
-pub fn add_book(
-    title: &str,
-    author: &str,
-    publication_year: i32,
-    isbn: &str,
-    available: bool,
-    conn: &mut PgConnection,
-) -> Result<uuid::Uuid> {
-    let book = NewBook {
-        title: title.to_string(),
-        author: author.to_string(),
-        publication_year,
-        isbn: Some(isbn.to_string()),
-        availability_status: available,
-    };
-
+pub fn add_book(book: NewBook, conn: &mut PgConnection) -> Result<uuid::Uuid> {
     let book_id = diesel::insert_into(books::table)
         .values(&book)
         .returning(books::book_id)
@@ -69,11 +77,7 @@ pub fn update_book_status(id: uuid::Uuid, new_val: bool, conn: &mut PgConnection
     diesel::update(books::table.filter(books::book_id.eq(id)))
         .set(books::availability_status.eq(&new_val))
         .execute(conn)
-        .with_context(|| {
-            LibError::ActixError(
-                ErrorInternalServerError(format!("failed to update book {id} status")).to_string(),
-            )
-        })
+        .with_context(|| LibError::DbError(format!("failed to update book {id} status")))
 }
 
 pub fn get_book(id: uuid::Uuid, conn: &mut PgConnection) -> Result<Option<Book>> {
@@ -83,9 +87,17 @@ pub fn get_book(id: uuid::Uuid, conn: &mut PgConnection) -> Result<Option<Book>>
         .optional()?)
 }
 
+pub fn get_book_by_barcode(barcode: &str, conn: &mut PgConnection) -> Result<Option<Book>> {
+    Ok(books::table
+        .filter(books::barcode.eq(barcode.trim()))
+        .first::<Book>(conn)
+        .optional()?)
+}
+
 pub fn update_book(id: uuid::Uuid, payload: NewBook, conn: &mut PgConnection) -> Result<bool> {
     use crate::schema::books::dsl::{
-        author, availability_status, book_id, books, isbn, publication_year, title,
+        author, availability_status, barcode, book_id, books, branch, isbn, item_type,
+        publication_year, replacement_cost_cents, title,
     };
 
     let num_updated = diesel::update(books.filter(book_id.eq(id)))
@@ -95,6 +107,10 @@ pub fn update_book(id: uuid::Uuid, payload: NewBook, conn: &mut PgConnection) ->
             publication_year.eq(payload.publication_year),
             isbn.eq(payload.isbn),
             availability_status.eq(payload.availability_status),
+            item_type.eq(payload.item_type),
+            branch.eq(payload.branch),
+            barcode.eq(payload.barcode),
+            replacement_cost_cents.eq(payload.replacement_cost_cents),
         ))
         .execute(conn)?;
 
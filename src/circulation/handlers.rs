@@ -0,0 +1,79 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    books::models::get_book_by_barcode,
+    db::establish_connection,
+    errors::error_response,
+    loans::models::{create_loan, get_loan, return_book, LoanStatus, NewLoan},
+    members::models::get_member_by_card,
+};
+use actix_web::{post, web, HttpResponse, Responder};
+
+use super::models::{find_book, get_open_loan, CheckinRequest, CheckoutRequest};
+
+/// Checks in the book in hand and reports the late fee charged and the hold
+/// the book now goes on the shelf for, if any.
+#[post("/circulation/checkin")]
+async fn checkin(principal: Principal, payload: web::Json<CheckinRequest>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::CheckIn) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    let book = match find_book(&payload, &mut conn) {
+        Ok(Some(book)) => book,
+        Ok(None) => return HttpResponse::NotFound().json("book not found"),
+        Err(e) => return error_response(&e),
+    };
+    let loan_id = match get_open_loan(book.book_id, &mut conn) {
+        Ok(Some(loan_id)) => loan_id,
+        Ok(None) => {
+            return HttpResponse::Conflict().json(format!("book {} is not on loan", book.book_id))
+        }
+        Err(e) => return error_response(&e),
+    };
+    match return_book(loan_id, LoanStatus::Returned, &mut conn).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Checks out the scanned book to the holder of the scanned library card.
+#[post("/circulation/checkout")]
+async fn checkout(principal: Principal, payload: web::Json<CheckoutRequest>) -> impl Responder {
+    let mut conn = establish_connection();
+    // a card the caller may not use reads the same as no card at all, so
+    // patrons cannot probe which card numbers exist
+    let member = match get_member_by_card(&payload.card_number, &mut conn) {
+        Ok(member) => member.filter(|member| {
+            principal
+                .require_for(member.member_id, Permission::CheckOut)
+                .is_ok()
+        }),
+        Err(e) => return error_response(&e),
+    };
+    let Some(member) = member else {
+        return HttpResponse::NotFound().json("card number not found");
+    };
+    if payload.due_date.is_some() {
+        if let Err(denied) = principal.require(Permission::OverrideDueDate) {
+            return denied;
+        }
+    }
+    let book = match get_book_by_barcode(&payload.barcode, &mut conn) {
+        Ok(Some(book)) => book,
+        Ok(None) => return HttpResponse::NotFound().json("barcode not found"),
+        Err(e) => return error_response(&e),
+    };
+
+    let request = NewLoan {
+        member_id: member.member_id,
+        book_id: book.book_id,
+        due_date: payload.due_date,
+    };
+    match create_loan(&request, &mut conn).await {
+        Ok(loan_id) => match get_loan(loan_id, &mut conn).await {
+            Ok(loan) => HttpResponse::Ok().json(loan),
+            Err(e) => error_response(&e),
+        },
+        Err(e) => error_response(&e),
+    }
+}
@@ -0,0 +1,60 @@
+use actix_web::error::ErrorBadRequest;
+use anyhow::Result;
+use chrono::NaiveDate;
+use diesel::{ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    books::models::{get_book, get_book_by_barcode, Book},
+    errors::LibError,
+    loans::models::LoanStatus,
+    schema::loans,
+};
+
+/// A book handed in at the desk, by id or by the barcode on its label.
+#[derive(Debug, Deserialize)]
+pub struct CheckinRequest {
+    pub book_id: Option<Uuid>,
+    pub barcode: Option<String>,
+}
+
+/// A book checked out at the desk by scanning the library card and the book.
+#[derive(Debug, Deserialize)]
+pub struct CheckoutRequest {
+    pub card_number: String,
+    pub barcode: String,
+    /// Overrides the due date from the circulation rules.
+    #[serde(default)]
+    pub due_date: Option<NaiveDate>,
+}
+
+/// Looks up the book of a check-in. Exactly one of `book_id` and `barcode`
+/// must be given.
+pub fn find_book(request: &CheckinRequest, conn: &mut PgConnection) -> Result<Option<Book>> {
+    match (request.book_id, request.barcode.as_deref()) {
+        (Some(book_id), None) => get_book(book_id, conn),
+        (None, Some(barcode)) => get_book_by_barcode(barcode, conn),
+        _ => Err(LibError::ActixError(
+            ErrorBadRequest("give either a book_id or a barcode").to_string(),
+        )
+        .into()),
+    }
+}
+
+/// The loan a book is out on, if any. A book reported lost or claimed
+/// returned that turns up at the desk is checked in on that loan.
+pub fn get_open_loan(book_id: Uuid, conn: &mut PgConnection) -> Result<Option<Uuid>> {
+    Ok(loans::table
+        .filter(loans::book_id.eq(book_id))
+        .filter(loans::status.eq_any([
+            LoanStatus::Open,
+            LoanStatus::Overdue,
+            LoanStatus::Lost,
+            LoanStatus::ClaimsReturned,
+        ]))
+        .order(loans::loan_date.desc())
+        .select(loans::loan_id)
+        .first(conn)
+        .optional()?)
+}
@@ -0,0 +1,67 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+};
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+
+use super::models::{
+    add_closure, delete_closure, get_closures, update_closure, ClosureFilter, ClosureRequest,
+};
+
+/// The days the library is closed, e.g. `?branch=main&from=2023-12-01`.
+#[get("/closures")]
+async fn fetch_closures(principal: Principal, filter: web::Query<ClosureFilter>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ReadBooks) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_closures(&filter, &mut conn) {
+        Ok(closures) => HttpResponse::Ok().json(closures),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[post("/admin/closures")]
+async fn create_closure(
+    principal: Principal,
+    payload: web::Json<ClosureRequest>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManagePolicies) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match add_closure(payload.into_inner(), &mut conn) {
+        Ok(closure_id) => HttpResponse::Ok().json(closure_id),
+        Err(e) => HttpResponse::BadRequest().json(format!("failed to add closure {e}")),
+    }
+}
+
+#[put("/admin/closures/{closure_id}")]
+async fn change_closure(
+    principal: Principal,
+    closure_id: web::Path<uuid::Uuid>,
+    payload: web::Json<ClosureRequest>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManagePolicies) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match update_closure(*closure_id, payload.into_inner(), &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::BadRequest().json(format!("failed to update closure {e}")),
+    }
+}
+
+#[delete("/admin/closures/{closure_id}")]
+async fn remove_closure(principal: Principal, closure_id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManagePolicies) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match delete_closure(*closure_id, &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeSet;
+
+use actix_web::error::ErrorBadRequest;
+use anyhow::Result;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use diesel::{
+    prelude::{Insertable, Queryable},
+    AsChangeset, BoolExpressionMethods, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+    Selectable, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{errors::LibError, schema::closures};
+
+/// How far [`Calendar::next_open_day`] looks for an open day before it gives
+/// up on a branch that never opens.
+const MAX_CLOSED_DAYS: i64 = 366;
+
+/// A day a branch is closed, `weekday` every week or `closed_on` once. A
+/// closure without a branch closes every branch.
+#[derive(Debug, Insertable, AsChangeset, Deserialize)]
+#[diesel(table_name = closures)]
+#[diesel(treat_none_as_null = true)]
+pub struct ClosureRequest {
+    pub branch: Option<String>,
+    /// ISO weekday, 1 is Monday and 7 is Sunday.
+    pub weekday: Option<i32>,
+    pub closed_on: Option<NaiveDate>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = closures)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Closure {
+    pub closure_id: Uuid,
+    pub branch: Option<String>,
+    pub weekday: Option<i32>,
+    pub closed_on: Option<NaiveDate>,
+    pub reason: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Narrows the closures listed to a branch, and the one-off ones to a range
+/// of dates. Weekly closures are always listed.
+#[derive(Debug, Default, Deserialize)]
+pub struct ClosureFilter {
+    pub branch: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+fn validate(closure: &ClosureRequest) -> Result<()> {
+    match (closure.weekday, closure.closed_on) {
+        (Some(weekday), None) if (1..=7).contains(&weekday) => Ok(()),
+        (Some(_), None) => Err(LibError::ActixError(
+            ErrorBadRequest("weekday must be between 1 (Monday) and 7 (Sunday)").to_string(),
+        )
+        .into()),
+        (None, Some(_)) => Ok(()),
+        _ => Err(LibError::ActixError(
+            ErrorBadRequest("a closure needs either a weekday or a closed_on date").to_string(),
+        )
+        .into()),
+    }
+}
+
+pub fn add_closure(closure: ClosureRequest, conn: &mut PgConnection) -> Result<Uuid> {
+    validate(&closure)?;
+    Ok(diesel::insert_into(closures::table)
+        .values(&closure)
+        .returning(closures::closure_id)
+        .get_result(conn)?)
+}
+
+/// Weekly closures first, then one-off ones by date.
+pub fn get_closures(filter: &ClosureFilter, conn: &mut PgConnection) -> Result<Vec<Closure>> {
+    let mut query = closures::table
+        .order((closures::weekday.asc(), closures::closed_on.asc()))
+        .select(Closure::as_select())
+        .into_boxed();
+    if let Some(branch) = &filter.branch {
+        query = query.filter(closures::branch.is_null().or(closures::branch.eq(branch)));
+    }
+    if let Some(from) = filter.from {
+        query = query.filter(
+            closures::closed_on
+                .is_null()
+                .or(closures::closed_on.ge(from)),
+        );
+    }
+    if let Some(to) = filter.to {
+        query = query.filter(closures::closed_on.is_null().or(closures::closed_on.le(to)));
+    }
+    Ok(query.load(conn)?)
+}
+
+pub fn update_closure(id: Uuid, closure: ClosureRequest, conn: &mut PgConnection) -> Result<bool> {
+    validate(&closure)?;
+    let num_updated = diesel::update(closures::table.filter(closures::closure_id.eq(id)))
+        .set(&closure)
+        .execute(conn)?;
+    Ok(num_updated > 0)
+}
+
+pub fn delete_closure(id: Uuid, conn: &mut PgConnection) -> Result<bool> {
+    let num_deleted =
+        diesel::delete(closures::table.filter(closures::closure_id.eq(id))).execute(conn)?;
+    Ok(num_deleted > 0)
+}
+
+/// The days one branch is closed, from some date on.
+#[derive(Debug, Default)]
+pub struct Calendar {
+    weekdays: BTreeSet<u32>,
+    days: BTreeSet<NaiveDate>,
+}
+
+impl Calendar {
+    pub fn is_closed(&self, date: NaiveDate) -> bool {
+        self.weekdays.contains(&date.weekday().number_from_monday()) || self.days.contains(&date)
+    }
+
+    /// `date` itself when the branch is open that day, or the first day after
+    /// it that it is. A branch closed for more than [`MAX_CLOSED_DAYS`] in a
+    /// row keeps `date`.
+    pub fn next_open_day(&self, date: NaiveDate) -> NaiveDate {
+        (0..MAX_CLOSED_DAYS)
+            .map(|days| date + Duration::days(days))
+            .find(|&day| !self.is_closed(day))
+            .unwrap_or(date)
+    }
+
+    /// How many days after `from`, up to and including `to`, the branch is
+    /// open.
+    pub fn open_days(&self, from: NaiveDate, to: NaiveDate) -> i64 {
+        (1..=(to - from).num_days())
+            .filter(|&days| !self.is_closed(from + Duration::days(days)))
+            .count() as i64
+    }
+}
+
+/// The calendar of `branch`, with the one-off closures from `since` on.
+pub fn get_calendar(branch: &str, since: NaiveDate, conn: &mut PgConnection) -> Result<Calendar> {
+    let closures: Vec<(Option<i32>, Option<NaiveDate>)> = closures::table
+        .filter(closures::branch.is_null().or(closures::branch.eq(branch)))
+        .filter(
+            closures::closed_on
+                .is_null()
+                .or(closures::closed_on.ge(since)),
+        )
+        .select((closures::weekday, closures::closed_on))
+        .load(conn)?;
+    let mut calendar = Calendar::default();
+    for closure in closures {
+        match closure {
+            (Some(weekday), _) => {
+                calendar.weekdays.insert(weekday as u32);
+            }
+            (None, Some(closed_on)) => {
+                calendar.days.insert(closed_on);
+            }
+            (None, None) => {}
+        }
+    }
+    Ok(calendar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 6, day).unwrap()
+    }
+
+    /// Closed on Sundays and on Monday the 12th; the 10th is a Saturday.
+    fn calendar() -> Calendar {
+        Calendar {
+            weekdays: BTreeSet::from([7]),
+            days: BTreeSet::from([date(12)]),
+        }
+    }
+
+    #[test]
+    fn open_days_are_kept() {
+        assert_eq!(calendar().next_open_day(date(10)), date(10));
+        assert_eq!(Calendar::default().next_open_day(date(11)), date(11));
+    }
+
+    #[test]
+    fn closed_days_move_to_the_next_open_day() {
+        assert_eq!(calendar().next_open_day(date(11)), date(13));
+        assert_eq!(calendar().next_open_day(date(12)), date(13));
+    }
+
+    #[test]
+    fn a_branch_that_never_opens_keeps_the_date() {
+        let calendar = Calendar {
+            weekdays: (1..=7).collect(),
+            days: BTreeSet::new(),
+        };
+        assert_eq!(calendar.next_open_day(date(10)), date(10));
+    }
+
+    #[test]
+    fn open_days_skip_closures_and_the_first_day() {
+        assert_eq!(calendar().open_days(date(10), date(10)), 0);
+        assert_eq!(calendar().open_days(date(10), date(12)), 0);
+        assert_eq!(calendar().open_days(date(10), date(14)), 2);
+        assert_eq!(calendar().open_days(date(9), date(10)), 1);
+        assert_eq!(Calendar::default().open_days(date(10), date(14)), 4);
+    }
+
+    #[test]
+    fn open_days_before_the_start_are_none() {
+        assert_eq!(calendar().open_days(date(14), date(10)), 0);
+    }
+}
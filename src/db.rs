@@ -1,6 +1,12 @@
 use std::env;
 
-use diesel::{Connection, PgConnection};
+use diesel::{
+    result::{DatabaseErrorKind, Error::DatabaseError},
+    sql_function,
+    sql_types::Nullable,
+    sql_types::Text,
+    Connection, PgConnection,
+};
 use dotenvy::dotenv;
 
 // #[derive(Debug, Serialize)]
@@ -15,6 +21,42 @@ use dotenvy::dotenv;
 //     }
 // }
 
+sql_function!(fn lower(x: Nullable<Text>) -> Nullable<Text>);
+
+/// How often [`with_retry`] tries again before giving up.
+const SERIALIZATION_RETRIES: usize = 3;
+
+/// Runs a transaction again when Postgres aborts it with a serialization
+/// failure or breaks a deadlock by aborting it. `run` must start its own
+/// transaction, since the aborted one cannot be reused.
+pub fn with_retry<T>(
+    conn: &mut PgConnection,
+    mut run: impl FnMut(&mut PgConnection) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match run(conn) {
+            Err(e) if attempt < SERIALIZATION_RETRIES && is_serialization_failure(&e) => {
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether Postgres aborted the transaction `e` came from and it has to be
+/// run again.
+pub fn is_serialization_failure(e: &anyhow::Error) -> bool {
+    match e.downcast_ref::<diesel::result::Error>() {
+        Some(DatabaseError(DatabaseErrorKind::SerializationFailure, _)) => true,
+        // diesel has no kind for deadlocks (40P01), only the message tells
+        Some(DatabaseError(DatabaseErrorKind::Unknown, info)) => {
+            info.message().starts_with("deadlock detected")
+        }
+        _ => false,
+    }
+}
+
 pub fn establish_connection() -> PgConnection {
     dotenv().ok();
 
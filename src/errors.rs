@@ -1,5 +1,12 @@
+use actix_web::HttpResponse;
+use serde_json::json;
 use thiserror::Error;
 
+use crate::{
+    blocks::models::MemberBlock,
+    loans::models::{BatchItem, RenewalRefusal},
+};
+
 // #[allow(dead_code)]
 #[derive(Debug, Error)]
 pub enum LibError {
@@ -9,8 +16,52 @@ pub enum LibError {
     DbError(String),
     #[error(transparent)]
     Io(#[from] std::io::Error),
-    #[error("chrono error: {0}")]
-    Chrono(String),
     #[error("diesel error: {0}")]
     Diesel(#[from] diesel::result::Error),
+    #[error("authentication error: {0}")]
+    Auth(String),
+    #[error("member has {} active block(s)", .0.len())]
+    MemberBlocked(Vec<MemberBlock>),
+    #[error("member owes {balance_cents} cents, loans are blocked from {threshold_cents} cents")]
+    BalanceTooHigh {
+        balance_cents: i64,
+        threshold_cents: i64,
+    },
+    #[error("loan cannot be renewed")]
+    RenewalRefused(Vec<RenewalRefusal>),
+    #[error("some books cannot be checked out, none were")]
+    BatchRefused(Vec<BatchItem>),
+    /// The request is fine but the record is not in a state that allows it,
+    /// e.g. erasing a member who still has books out.
+    #[error("{0}")]
+    Conflict(String),
+}
+
+/// Turns a model error into a response, with a status and body that fit the
+/// error where it is one the client can act on.
+pub fn error_response(e: &anyhow::Error) -> HttpResponse {
+    match e.downcast_ref::<LibError>() {
+        Some(LibError::MemberBlocked(blocks)) => {
+            HttpResponse::Forbidden().json(json!({ "error": e.to_string(), "blocks": blocks }))
+        }
+        Some(LibError::BalanceTooHigh {
+            balance_cents,
+            threshold_cents,
+        }) => HttpResponse::Forbidden().json(json!({
+            "error": e.to_string(),
+            "balance_cents": balance_cents,
+            "threshold_cents": threshold_cents,
+        })),
+        Some(LibError::RenewalRefused(reasons)) => {
+            HttpResponse::Conflict().json(json!({ "error": e.to_string(), "reasons": reasons }))
+        }
+        Some(LibError::BatchRefused(items)) => {
+            HttpResponse::Conflict().json(json!({ "error": e.to_string(), "items": items }))
+        }
+        Some(LibError::Conflict(_)) => HttpResponse::Conflict().json(format!("{e}")),
+        // models only report client errors this way, see LibError::DbError for
+        // the rest
+        Some(LibError::ActixError(_)) => HttpResponse::BadRequest().json(format!("{e}")),
+        _ => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
 }
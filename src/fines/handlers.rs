@@ -0,0 +1,87 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+    errors::error_response,
+    members::models::get_member,
+};
+use actix_web::{get, post, web, HttpResponse, Responder};
+
+use super::models::{get_account, settle, EntryType, SettlementRequest};
+
+#[get("/members/{member_id}/account")]
+async fn fetch_account(principal: Principal, member_id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require_for(*member_id, Permission::ReadMembers) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+
+    match get_member(*member_id, &mut conn) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(format!("member {member_id} not found")),
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+
+    match get_account(*member_id, &mut conn) {
+        Ok(account) => HttpResponse::Ok().json(account),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+async fn record_settlement(
+    principal: Principal,
+    member_id: uuid::Uuid,
+    entry_type: EntryType,
+    payload: SettlementRequest,
+) -> HttpResponse {
+    if let Err(denied) = principal.require(Permission::ManageFines) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+
+    match get_member(member_id, &mut conn) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(format!("member {member_id} not found")),
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+
+    match settle(
+        member_id,
+        entry_type,
+        payload,
+        principal.member_id,
+        &mut conn,
+    ) {
+        Ok(entry_id) => HttpResponse::Ok().json(entry_id),
+        Err(e) => error_response(&e),
+    }
+}
+
+#[post("/members/{member_id}/payments")]
+async fn add_payment(
+    principal: Principal,
+    member_id: web::Path<uuid::Uuid>,
+    payload: web::Json<SettlementRequest>,
+) -> impl Responder {
+    record_settlement(
+        principal,
+        *member_id,
+        EntryType::Payment,
+        payload.into_inner(),
+    )
+    .await
+}
+
+#[post("/members/{member_id}/waivers")]
+async fn add_waiver(
+    principal: Principal,
+    member_id: web::Path<uuid::Uuid>,
+    payload: web::Json<SettlementRequest>,
+) -> impl Responder {
+    record_settlement(
+        principal,
+        *member_id,
+        EntryType::Waiver,
+        payload.into_inner(),
+    )
+    .await
+}
@@ -0,0 +1,312 @@
+use std::io::Write;
+
+use actix_web::error::ErrorBadRequest;
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::{
+    deserialize::FromSql,
+    pg::{Pg, PgValue},
+    prelude::Queryable,
+    serialize::{IsNull, ToSql},
+    AsExpression, Connection, ExpressionMethods, FromSqlRow, OptionalExtension, PgConnection,
+    QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    books::models::get_book,
+    closures::models::get_calendar,
+    errors::LibError,
+    loans::models::Loan,
+    members::models::Member,
+    policy::models::{require_rule, LoanContext},
+    schema::fines,
+};
+
+/// Balance from which a member may not borrow, unless `FINE_BLOCK_THRESHOLD_CENTS`
+/// says otherwise.
+pub const DEFAULT_BLOCK_THRESHOLD_CENTS: i64 = 1000;
+
+/// What a lost book is billed at when it has no replacement cost of its own,
+/// unless `REPLACEMENT_COST_CENTS` says otherwise.
+pub const DEFAULT_REPLACEMENT_COST_CENTS: i32 = 2500;
+
+pub fn replacement_cost_cents() -> i32 {
+    std::env::var("REPLACEMENT_COST_CENTS")
+        .ok()
+        .and_then(|cents| cents.parse().ok())
+        .unwrap_or(DEFAULT_REPLACEMENT_COST_CENTS)
+}
+
+pub fn block_threshold_cents() -> i64 {
+    std::env::var("FINE_BLOCK_THRESHOLD_CENTS")
+        .ok()
+        .and_then(|cents| cents.parse().ok())
+        .unwrap_or(DEFAULT_BLOCK_THRESHOLD_CENTS)
+}
+
+#[derive(Debug, PartialEq, FromSqlRow, AsExpression, Eq, Serialize, Deserialize, Clone, Copy)]
+#[diesel(sql_type = crate::schema::sql_types::FineEntryType)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryType {
+    Charge,
+    Payment,
+    Waiver,
+    /// The cost of replacing a lost book.
+    Replacement,
+    /// Gives back a replacement charge once the book turns up.
+    Refund,
+}
+
+impl ToSql<crate::schema::sql_types::FineEntryType, Pg> for EntryType {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        match *self {
+            EntryType::Charge => out.write_all(b"charge")?,
+            EntryType::Payment => out.write_all(b"payment")?,
+            EntryType::Waiver => out.write_all(b"waiver")?,
+            EntryType::Replacement => out.write_all(b"replacement")?,
+            EntryType::Refund => out.write_all(b"refund")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<crate::schema::sql_types::FineEntryType, Pg> for EntryType {
+    fn from_sql(bytes: PgValue<'_>) -> diesel::deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"charge" => Ok(EntryType::Charge),
+            b"payment" => Ok(EntryType::Payment),
+            b"waiver" => Ok(EntryType::Waiver),
+            b"replacement" => Ok(EntryType::Replacement),
+            b"refund" => Ok(EntryType::Refund),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = fines)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct LedgerEntry {
+    pub entry_id: Uuid,
+    pub member_id: Uuid,
+    pub loan_id: Option<Uuid>,
+    pub entry_type: EntryType,
+    pub amount_cents: i32,
+    pub note: Option<String>,
+    pub created_by: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+}
+
+/// A payment or waiver settling part of the balance of a member.
+#[derive(Debug, Deserialize)]
+pub struct SettlementRequest {
+    pub amount_cents: i32,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Account {
+    pub member_id: Uuid,
+    pub balance_cents: i64,
+    pub block_threshold_cents: i64,
+    /// Whether the balance keeps the member from borrowing.
+    pub blocked: bool,
+    pub entries: Vec<LedgerEntry>,
+}
+
+/// What a member owes: charges and replacements minus payments, waivers and
+/// refunds. Negative when a refund leaves the member in credit.
+pub fn get_balance(member_id: Uuid, conn: &mut PgConnection) -> Result<i64> {
+    let totals: Vec<(EntryType, Option<i64>)> = fines::table
+        .filter(fines::member_id.eq(member_id))
+        .group_by(fines::entry_type)
+        .select((fines::entry_type, diesel::dsl::sum(fines::amount_cents)))
+        .load(conn)?;
+    Ok(totals
+        .into_iter()
+        .map(|(entry_type, total)| match entry_type {
+            EntryType::Charge | EntryType::Replacement => total.unwrap_or(0),
+            EntryType::Payment | EntryType::Waiver | EntryType::Refund => -total.unwrap_or(0),
+        })
+        .sum())
+}
+
+pub fn get_account(member_id: Uuid, conn: &mut PgConnection) -> Result<Account> {
+    let balance_cents = get_balance(member_id, conn)?;
+    let block_threshold_cents = block_threshold_cents();
+    Ok(Account {
+        member_id,
+        balance_cents,
+        block_threshold_cents,
+        blocked: balance_cents >= block_threshold_cents,
+        entries: fines::table
+            .filter(fines::member_id.eq(member_id))
+            .order(fines::created_at.desc())
+            .select(LedgerEntry::as_select())
+            .load(conn)?,
+    })
+}
+
+/// Refuses members who owe [`block_threshold_cents`] or more.
+pub fn check_balance(member_id: Uuid, conn: &mut PgConnection) -> Result<()> {
+    let balance_cents = get_balance(member_id, conn)?;
+    let threshold_cents = block_threshold_cents();
+    if balance_cents >= threshold_cents {
+        return Err(LibError::BalanceTooHigh {
+            balance_cents,
+            threshold_cents,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Brings the late fee of a loan up to date as of `as_of`, following the
+/// circulation rule of the loan, and returns how many cents the fee went up.
+///
+/// Each loan has a single charge that is raised on every call, so this can run
+/// as often as needed. A fee never shrinks; waivers are recorded separately.
+pub fn assess_fine(
+    loan: &Loan,
+    member: &Member,
+    as_of: NaiveDate,
+    conn: &mut PgConnection,
+) -> Result<i32> {
+    let book = get_book(loan.book_id, conn)?
+        .ok_or_else(|| LibError::DbError(format!("book {} not found", loan.book_id)))?;
+    let calendar = get_calendar(&book.branch, loan.due_date, conn)?;
+    let rule = require_rule(
+        &LoanContext {
+            tier: member.tier.clone(),
+            item_type: book.item_type,
+            branch: book.branch,
+        },
+        conn,
+    )?;
+    let amount_cents = rule.fine_cents(loan.due_date, as_of, &calendar, loan.recalled_at.is_some());
+    if amount_cents == 0 {
+        return Ok(0);
+    }
+
+    conn.transaction(|conn| {
+        let charged: Option<(Uuid, i32)> = fines::table
+            .filter(fines::loan_id.eq(loan.loan_id))
+            .filter(fines::entry_type.eq(EntryType::Charge))
+            .select((fines::entry_id, fines::amount_cents))
+            .first(conn)
+            .optional()?;
+        match charged {
+            Some((_, charged_cents)) if charged_cents >= amount_cents => Ok(0),
+            Some((entry_id, charged_cents)) => {
+                diesel::update(fines::table.find(entry_id))
+                    .set(fines::amount_cents.eq(amount_cents))
+                    .execute(conn)?;
+                Ok(amount_cents - charged_cents)
+            }
+            None => {
+                diesel::insert_into(fines::table)
+                    .values((
+                        fines::member_id.eq(member.member_id),
+                        fines::loan_id.eq(loan.loan_id),
+                        fines::entry_type.eq(EntryType::Charge),
+                        fines::amount_cents.eq(amount_cents),
+                        fines::note.eq(format!("late fee, due {}", loan.due_date)),
+                    ))
+                    .execute(conn)?;
+                Ok(amount_cents)
+            }
+        }
+    })
+}
+
+/// Bills `member_id` the replacement cost of the book of a lost loan and
+/// returns the amount billed.
+pub fn bill_replacement(loan: &Loan, member_id: Uuid, conn: &mut PgConnection) -> Result<i32> {
+    let book = get_book(loan.book_id, conn)?
+        .ok_or_else(|| LibError::DbError(format!("book {} not found", loan.book_id)))?;
+    let amount_cents = book
+        .replacement_cost_cents
+        .unwrap_or_else(replacement_cost_cents);
+    if amount_cents == 0 {
+        return Ok(0);
+    }
+    diesel::insert_into(fines::table)
+        .values((
+            fines::member_id.eq(member_id),
+            fines::loan_id.eq(loan.loan_id),
+            fines::entry_type.eq(EntryType::Replacement),
+            fines::amount_cents.eq(amount_cents),
+            fines::note.eq(format!("replacement of \"{}\"", book.title)),
+        ))
+        .execute(conn)?;
+    Ok(amount_cents)
+}
+
+/// Refunds what was billed for replacing the book of a loan that turned up
+/// after all, and returns the amount refunded.
+pub fn refund_replacement(loan: &Loan, member_id: Uuid, conn: &mut PgConnection) -> Result<i32> {
+    let totals: Vec<(EntryType, Option<i64>)> = fines::table
+        .filter(fines::loan_id.eq(loan.loan_id))
+        .filter(fines::entry_type.eq_any([EntryType::Replacement, EntryType::Refund]))
+        .group_by(fines::entry_type)
+        .select((fines::entry_type, diesel::dsl::sum(fines::amount_cents)))
+        .load(conn)?;
+    let owed: i64 = totals
+        .into_iter()
+        .map(|(entry_type, total)| match entry_type {
+            EntryType::Replacement => total.unwrap_or(0),
+            _ => -total.unwrap_or(0),
+        })
+        .sum();
+    if owed <= 0 {
+        return Ok(0);
+    }
+    let amount_cents = i32::try_from(owed)?;
+    diesel::insert_into(fines::table)
+        .values((
+            fines::member_id.eq(member_id),
+            fines::loan_id.eq(loan.loan_id),
+            fines::entry_type.eq(EntryType::Refund),
+            fines::amount_cents.eq(amount_cents),
+            fines::note.eq("lost book returned"),
+        ))
+        .execute(conn)?;
+    Ok(amount_cents)
+}
+
+/// Records a payment or waiver. Refuses amounts the member does not owe.
+pub fn settle(
+    member_id: Uuid,
+    entry_type: EntryType,
+    request: SettlementRequest,
+    created_by: Option<Uuid>,
+    conn: &mut PgConnection,
+) -> Result<Uuid> {
+    conn.transaction(|conn| {
+        let balance_cents = get_balance(member_id, conn)?;
+        if request.amount_cents <= 0 || i64::from(request.amount_cents) > balance_cents {
+            return Err(LibError::ActixError(
+                ErrorBadRequest(format!(
+                    "amount must be between 1 and the balance of {balance_cents} cents"
+                ))
+                .to_string(),
+            )
+            .into());
+        }
+        Ok(diesel::insert_into(fines::table)
+            .values((
+                fines::member_id.eq(member_id),
+                fines::entry_type.eq(entry_type),
+                fines::amount_cents.eq(request.amount_cents),
+                fines::note.eq(request.note),
+                fines::created_by.eq(created_by),
+            ))
+            .returning(fines::entry_id)
+            .get_result(conn)?)
+    })
+}
@@ -0,0 +1,94 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+    errors::error_response,
+    members::models::get_member,
+};
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+
+use super::models::{
+    cancel_hold, expire_holds, get_book_holds, get_hold, get_member_holds, place_hold, HoldRequest,
+    HoldsFilter,
+};
+
+#[post("/books/{book_id}/holds")]
+async fn add_hold(
+    principal: Principal,
+    book_id: web::Path<uuid::Uuid>,
+    payload: web::Json<HoldRequest>,
+) -> impl Responder {
+    if let Err(denied) = principal.require_for(payload.member_id, Permission::PlaceHolds) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match place_hold(*book_id, payload.member_id, &mut conn) {
+        Ok(hold_id) => HttpResponse::Ok().json(hold_id),
+        Err(e) => error_response(&e),
+    }
+}
+
+#[get("/books/{book_id}/holds")]
+async fn fetch_book_holds(principal: Principal, book_id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageHolds) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_book_holds(*book_id, &mut conn) {
+        Ok(holds) => HttpResponse::Ok().json(holds),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[delete("/holds/{hold_id}")]
+async fn remove_hold(principal: Principal, hold_id: web::Path<uuid::Uuid>) -> impl Responder {
+    let mut conn = establish_connection();
+    let hold = match get_hold(*hold_id, &mut conn) {
+        Ok(Some(hold)) => hold,
+        Ok(None) => return HttpResponse::NotFound().json(format!("hold {hold_id} not found")),
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    };
+    if let Err(denied) = principal.require_for(hold.member_id, Permission::PlaceHolds) {
+        return denied;
+    }
+    match cancel_hold(*hold_id, &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::BadRequest().json(format!("failed to cancel hold {e}")),
+    }
+}
+
+#[get("/members/{member_id}/holds")]
+async fn fetch_member_holds(
+    principal: Principal,
+    member_id: web::Path<uuid::Uuid>,
+    filter: web::Query<HoldsFilter>,
+) -> impl Responder {
+    if let Err(denied) = principal.require_for(*member_id, Permission::ReadLoans) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+
+    match get_member(*member_id, &mut conn) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(format!("member {member_id} not found")),
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+
+    match get_member_holds(*member_id, filter.active, &mut conn) {
+        Ok(holds) => HttpResponse::Ok().json(holds),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+/// Expires holds nobody picked up in time.
+#[post("/admin/holds/expire")]
+async fn expire(principal: Principal) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageHolds) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match expire_holds(chrono::Utc::now().date_naive(), &mut conn) {
+        Ok(expired) => HttpResponse::Ok().json(expired),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
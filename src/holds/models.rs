@@ -0,0 +1,375 @@
+use std::io::Write;
+
+use actix_web::error::ErrorBadRequest;
+use anyhow::Result;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use diesel::{
+    deserialize::FromSql,
+    pg::{Pg, PgValue},
+    prelude::Queryable,
+    serialize::{IsNull, ToSql},
+    AsExpression, Connection, ExpressionMethods, FromSqlRow, OptionalExtension, PgConnection,
+    PgSortExpressionMethods, QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    blocks::models::check_member_blocks,
+    books::models::{get_book, update_book_status},
+    errors::LibError,
+    ill::models::get_borrowed_copy,
+    loans::models::LoanStatus,
+    members::models::get_member,
+    schema::{books, holds, loans},
+};
+
+/// How long a book waits on the hold shelf before the hold expires.
+pub const PICKUP_DAYS: i64 = 7;
+
+#[derive(Debug, PartialEq, FromSqlRow, AsExpression, Eq, Serialize, Deserialize, Clone, Copy)]
+#[diesel(sql_type = crate::schema::sql_types::HoldStatus)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldStatus {
+    /// In the queue for the book.
+    Waiting,
+    /// On the hold shelf, waiting to be picked up.
+    Ready,
+    Fulfilled,
+    Cancelled,
+    Expired,
+}
+
+impl ToSql<crate::schema::sql_types::HoldStatus, Pg> for HoldStatus {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        match *self {
+            HoldStatus::Waiting => out.write_all(b"waiting")?,
+            HoldStatus::Ready => out.write_all(b"ready")?,
+            HoldStatus::Fulfilled => out.write_all(b"fulfilled")?,
+            HoldStatus::Cancelled => out.write_all(b"cancelled")?,
+            HoldStatus::Expired => out.write_all(b"expired")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<crate::schema::sql_types::HoldStatus, Pg> for HoldStatus {
+    fn from_sql(bytes: PgValue<'_>) -> diesel::deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"waiting" => Ok(HoldStatus::Waiting),
+            b"ready" => Ok(HoldStatus::Ready),
+            b"fulfilled" => Ok(HoldStatus::Fulfilled),
+            b"cancelled" => Ok(HoldStatus::Cancelled),
+            b"expired" => Ok(HoldStatus::Expired),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+pub const ACTIVE: [HoldStatus; 2] = [HoldStatus::Waiting, HoldStatus::Ready];
+
+#[derive(Debug, Deserialize)]
+pub struct HoldRequest {
+    pub member_id: Uuid,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = holds)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Hold {
+    pub hold_id: Uuid,
+    pub book_id: Uuid,
+    pub member_id: Uuid,
+    pub status: HoldStatus,
+    pub placed_at: NaiveDateTime,
+    pub ready_at: Option<NaiveDateTime>,
+    pub pickup_expires_on: Option<NaiveDate>,
+    pub closed_at: Option<NaiveDateTime>,
+}
+
+/// A hold as shown to the member who placed it.
+#[derive(Debug, Serialize)]
+pub struct MemberHold {
+    pub hold_id: Uuid,
+    pub book_id: Uuid,
+    pub title: String,
+    pub status: HoldStatus,
+    pub placed_at: NaiveDateTime,
+    pub pickup_expires_on: Option<NaiveDate>,
+    /// Place in the queue for waiting holds, starting at 1.
+    pub position: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HoldsFilter {
+    pub active: Option<bool>,
+}
+
+fn bad_request(message: String) -> anyhow::Error {
+    LibError::ActixError(ErrorBadRequest(message).to_string()).into()
+}
+
+/// Queues `member_id` for a book that is currently out.
+pub fn place_hold(book_id: Uuid, member_id: Uuid, conn: &mut PgConnection) -> Result<Uuid> {
+    conn.transaction(|conn| {
+        let book = get_book(book_id, conn)?
+            .ok_or_else(|| LibError::DbError(format!("book {book_id} not found")))?;
+        if book.availability_status {
+            return Err(bad_request(format!(
+                "book {book_id} is available, borrow it instead"
+            )));
+        }
+        if get_borrowed_copy(book_id, conn)?.is_some() {
+            return Err(bad_request(format!(
+                "book {book_id} was borrowed from a partner library and goes back to it"
+            )));
+        }
+        let member = get_member(member_id, conn)?
+            .ok_or_else(|| LibError::DbError(format!("member {member_id} not found")))?;
+        if member.anonymized_at.is_some() {
+            return Err(bad_request(
+                "Member account has been anonymized".to_string(),
+            ));
+        }
+        check_member_blocks(member_id, conn)?;
+
+        let borrowing: i64 = loans::table
+            .filter(loans::book_id.eq(book_id))
+            .filter(loans::member_id.eq(member_id))
+            .filter(loans::status.eq_any([LoanStatus::Open, LoanStatus::Overdue]))
+            .count()
+            .get_result(conn)?;
+        if borrowing > 0 {
+            return Err(bad_request(format!(
+                "member {member_id} already has book {book_id} on loan"
+            )));
+        }
+        let queued: i64 = holds::table
+            .filter(holds::book_id.eq(book_id))
+            .filter(holds::member_id.eq(member_id))
+            .filter(holds::status.eq_any(ACTIVE))
+            .count()
+            .get_result(conn)?;
+        if queued > 0 {
+            return Err(bad_request(format!(
+                "member {member_id} already has a hold on book {book_id}"
+            )));
+        }
+
+        Ok(diesel::insert_into(holds::table)
+            .values((holds::book_id.eq(book_id), holds::member_id.eq(member_id)))
+            .returning(holds::hold_id)
+            .get_result(conn)?)
+    })
+}
+
+/// The hold `member_id` has waiting or ready for a book, if any.
+pub fn get_active_hold(
+    book_id: Uuid,
+    member_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<Option<Uuid>> {
+    Ok(holds::table
+        .filter(holds::book_id.eq(book_id))
+        .filter(holds::member_id.eq(member_id))
+        .filter(holds::status.eq_any(ACTIVE))
+        .select(holds::hold_id)
+        .first(conn)
+        .optional()?)
+}
+
+pub fn get_hold(id: Uuid, conn: &mut PgConnection) -> Result<Option<Hold>> {
+    Ok(holds::table
+        .find(id)
+        .select(Hold::as_select())
+        .first(conn)
+        .optional()?)
+}
+
+/// Hands a book that just came back to the next member in its queue, putting
+/// it on the hold shelf until `today` + [`PICKUP_DAYS`]. Only makes the book
+/// available to everyone when nobody is waiting. Returns the hold that is now
+/// ready, if any.
+///
+/// A copy borrowed from a partner library skips the queue and stays available,
+/// so it can be sent back.
+pub fn advance_queue(
+    book_id: Uuid,
+    today: NaiveDate,
+    conn: &mut PgConnection,
+) -> Result<Option<Hold>> {
+    conn.transaction(|conn| {
+        if get_borrowed_copy(book_id, conn)?.is_some() {
+            update_book_status(book_id, true, conn)?;
+            return Ok(None);
+        }
+        let next: Option<Uuid> = holds::table
+            .filter(holds::book_id.eq(book_id))
+            .filter(holds::status.eq(HoldStatus::Waiting))
+            .order(holds::placed_at)
+            .select(holds::hold_id)
+            .first(conn)
+            .optional()?;
+        let Some(hold_id) = next else {
+            update_book_status(book_id, true, conn)?;
+            return Ok(None);
+        };
+
+        update_book_status(book_id, false, conn)?;
+        Ok(Some(
+            diesel::update(holds::table.find(hold_id))
+                .set((
+                    holds::status.eq(HoldStatus::Ready),
+                    holds::ready_at.eq(chrono::Utc::now().naive_utc()),
+                    holds::pickup_expires_on.eq(today + Duration::days(PICKUP_DAYS)),
+                ))
+                .returning(Hold::as_returning())
+                .get_result(conn)?,
+        ))
+    })
+}
+
+/// Marks the hold `member_id` has ready for `book_id` as picked up, if there
+/// is one. Used when the book is checked out from the hold shelf.
+pub fn fulfil_hold(book_id: Uuid, member_id: Uuid, conn: &mut PgConnection) -> Result<bool> {
+    let num_updated = diesel::update(
+        holds::table
+            .filter(holds::book_id.eq(book_id))
+            .filter(holds::member_id.eq(member_id))
+            .filter(holds::status.eq(HoldStatus::Ready)),
+    )
+    .set((
+        holds::status.eq(HoldStatus::Fulfilled),
+        holds::closed_at.eq(chrono::Utc::now().naive_utc()),
+    ))
+    .execute(conn)?;
+    Ok(num_updated > 0)
+}
+
+/// Closes an active hold as cancelled or expired. A hold that was on the hold
+/// shelf passes the book on to the next member in the queue.
+fn close_hold(hold: &Hold, status: HoldStatus, conn: &mut PgConnection) -> Result<()> {
+    diesel::update(holds::table.find(hold.hold_id))
+        .set((
+            holds::status.eq(status),
+            holds::closed_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    if hold.status == HoldStatus::Ready {
+        advance_queue(hold.book_id, chrono::Utc::now().date_naive(), conn)?;
+    }
+    Ok(())
+}
+
+pub fn cancel_hold(id: Uuid, conn: &mut PgConnection) -> Result<bool> {
+    conn.transaction(|conn| {
+        let Some(hold) = get_hold(id, conn)? else {
+            return Ok(false);
+        };
+        if !ACTIVE.contains(&hold.status) {
+            return Err(bad_request(format!("hold {id} is no longer active")));
+        }
+        close_hold(&hold, HoldStatus::Cancelled, conn)?;
+        Ok(true)
+    })
+}
+
+/// Cancels every active hold of a member, e.g. before the member is deleted or
+/// anonymized.
+pub fn cancel_member_holds(member_id: Uuid, conn: &mut PgConnection) -> Result<usize> {
+    conn.transaction(|conn| {
+        let active: Vec<Hold> = holds::table
+            .filter(holds::member_id.eq(member_id))
+            .filter(holds::status.eq_any(ACTIVE))
+            .select(Hold::as_select())
+            .load(conn)?;
+        for hold in &active {
+            close_hold(hold, HoldStatus::Cancelled, conn)?;
+        }
+        Ok(active.len())
+    })
+}
+
+/// Expires holds left on the hold shelf past their pickup date and passes the
+/// books on. Returns the expired holds.
+pub fn expire_holds(today: NaiveDate, conn: &mut PgConnection) -> Result<Vec<Uuid>> {
+    conn.transaction(|conn| {
+        let stale: Vec<Hold> = holds::table
+            .filter(holds::status.eq(HoldStatus::Ready))
+            .filter(holds::pickup_expires_on.lt(today))
+            .select(Hold::as_select())
+            .load(conn)?;
+        for hold in &stale {
+            close_hold(hold, HoldStatus::Expired, conn)?;
+        }
+        Ok(stale.into_iter().map(|hold| hold.hold_id).collect())
+    })
+}
+
+/// How many members other than `member_id` are waiting for a book.
+pub fn count_waiting(book_id: Uuid, member_id: Uuid, conn: &mut PgConnection) -> Result<i64> {
+    Ok(holds::table
+        .filter(holds::book_id.eq(book_id))
+        .filter(holds::member_id.ne(member_id))
+        .filter(holds::status.eq(HoldStatus::Waiting))
+        .count()
+        .get_result(conn)?)
+}
+
+/// The holds of a member, newest first, with their place in the queue.
+pub fn get_member_holds(
+    member_id: Uuid,
+    active: Option<bool>,
+    conn: &mut PgConnection,
+) -> Result<Vec<MemberHold>> {
+    let mut query = holds::table
+        .inner_join(books::table)
+        .filter(holds::member_id.eq(member_id))
+        .select((Hold::as_select(), books::title))
+        .order(holds::placed_at.desc())
+        .into_boxed();
+    query = match active {
+        Some(true) => query.filter(holds::status.eq_any(ACTIVE)),
+        Some(false) => query.filter(holds::status.ne_all(ACTIVE)),
+        None => query,
+    };
+    let rows: Vec<(Hold, String)> = query.load(conn)?;
+
+    rows.into_iter()
+        .map(|(hold, title)| {
+            let position = if hold.status == HoldStatus::Waiting {
+                let ahead: i64 = holds::table
+                    .filter(holds::book_id.eq(hold.book_id))
+                    .filter(holds::status.eq(HoldStatus::Waiting))
+                    .filter(holds::placed_at.lt(hold.placed_at))
+                    .count()
+                    .get_result(conn)?;
+                Some(ahead + 1)
+            } else {
+                None
+            };
+            Ok(MemberHold {
+                hold_id: hold.hold_id,
+                book_id: hold.book_id,
+                title,
+                status: hold.status,
+                placed_at: hold.placed_at,
+                pickup_expires_on: hold.pickup_expires_on,
+                position,
+            })
+        })
+        .collect()
+}
+
+/// The active holds on a book in queue order, the one on the hold shelf first.
+pub fn get_book_holds(book_id: Uuid, conn: &mut PgConnection) -> Result<Vec<Hold>> {
+    Ok(holds::table
+        .filter(holds::book_id.eq(book_id))
+        .filter(holds::status.eq_any(ACTIVE))
+        .order((holds::ready_at.desc().nulls_last(), holds::placed_at))
+        .select(Hold::as_select())
+        .load(conn)?)
+}
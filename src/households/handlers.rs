@@ -0,0 +1,158 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+    errors::error_response,
+    loans::models::{get_loan, get_member_loans, renew_loan, MemberLoansFilter},
+    members::models::get_member,
+    pagination::Pagination,
+};
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::Serialize;
+
+use super::models::{
+    add_dependent as link_dependent, get_dependents, get_guardians, is_guardian_of,
+    remove_dependent as unlink_dependent, DependentRequest,
+};
+
+#[derive(Debug, Serialize)]
+struct DependentLoans {
+    member_id: uuid::Uuid,
+    name: String,
+    loans: crate::loans::models::MemberLoans,
+}
+
+#[post("/members/{member_id}/dependents")]
+async fn add_dependent(
+    principal: Principal,
+    guardian_id: web::Path<uuid::Uuid>,
+    payload: web::Json<DependentRequest>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageHouseholds) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    for id in [*guardian_id, payload.dependent_id] {
+        match get_member(id, &mut conn) {
+            Ok(Some(_)) => {}
+            Ok(None) => return HttpResponse::NotFound().json(format!("member {id} not found")),
+            Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+        }
+    }
+
+    match link_dependent(*guardian_id, payload.dependent_id, &mut conn) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => HttpResponse::BadRequest().json(format!("{e}")),
+    }
+}
+
+#[get("/members/{member_id}/dependents")]
+async fn fetch_dependents(
+    principal: Principal,
+    guardian_id: web::Path<uuid::Uuid>,
+) -> impl Responder {
+    if let Err(denied) = principal.require_for(*guardian_id, Permission::ReadMembers) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_dependents(*guardian_id, &mut conn) {
+        Ok(dependents) => HttpResponse::Ok().json(dependents),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[get("/members/{member_id}/guardians")]
+async fn fetch_guardians(
+    principal: Principal,
+    dependent_id: web::Path<uuid::Uuid>,
+) -> impl Responder {
+    if let Err(denied) = principal.require_for(*dependent_id, Permission::ReadMembers) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_guardians(*dependent_id, &mut conn) {
+        Ok(guardians) => HttpResponse::Ok().json(guardians),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[delete("/members/{member_id}/dependents/{dependent_id}")]
+async fn remove_dependent(
+    principal: Principal,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageHouseholds) {
+        return denied;
+    }
+    let (guardian_id, dependent_id) = path.into_inner();
+    let mut conn = establish_connection();
+    match unlink_dependent(guardian_id, dependent_id, &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[get("/members/{member_id}/dependents/loans")]
+async fn fetch_dependent_loans(
+    principal: Principal,
+    guardian_id: web::Path<uuid::Uuid>,
+    filter: web::Query<MemberLoansFilter>,
+    pagination: web::Query<Pagination>,
+) -> impl Responder {
+    if let Err(denied) = principal.require_for(*guardian_id, Permission::ReadLoans) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    let dependents = match get_dependents(*guardian_id, &mut conn) {
+        Ok(dependents) => dependents,
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    };
+
+    let mut household = Vec::with_capacity(dependents.len());
+    for dependent in dependents {
+        match get_member_loans(dependent.member_id, filter.status, *pagination, &mut conn).await {
+            Ok(loans) => household.push(DependentLoans {
+                member_id: dependent.member_id,
+                name: dependent.name,
+                loans,
+            }),
+            Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+        }
+    }
+    HttpResponse::Ok().json(household)
+}
+
+#[post("/members/{member_id}/dependents/loans/{loan_id}/renew")]
+async fn renew_dependent_loan(
+    principal: Principal,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+) -> impl Responder {
+    let (guardian_id, loan_id) = path.into_inner();
+    if let Err(denied) = principal.require_for(guardian_id, Permission::RenewLoans) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+
+    let loan = match get_loan(loan_id, &mut conn).await {
+        Ok(Some(loan)) => loan,
+        Ok(None) => return HttpResponse::NotFound().json(format!("loan {loan_id} not found")),
+        Err(e) => return error_response(&e),
+    };
+    let borrower = loan.member_id.map_or(Ok(false), |borrower| {
+        is_guardian_of(guardian_id, borrower, &mut conn)
+    });
+    match borrower {
+        Ok(true) => {}
+        Ok(false) => {
+            return HttpResponse::Forbidden().json(format!(
+                "member {guardian_id} is not a guardian of the borrower of loan {loan_id}"
+            ))
+        }
+        Err(e) => return error_response(&e),
+    }
+
+    match renew_loan(loan_id, &mut conn).await {
+        Ok(due_date) => HttpResponse::Ok().json(due_date),
+        Err(e) => error_response(&e),
+    }
+}
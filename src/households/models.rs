@@ -0,0 +1,163 @@
+use actix_web::error::ErrorBadRequest;
+use anyhow::Result;
+use diesel::{
+    prelude::Insertable, BoolExpressionMethods, Connection, ExpressionMethods, PgConnection,
+    QueryDsl, RunQueryDsl,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    errors::LibError,
+    members::models::Member,
+    schema::{member_relationships, members},
+};
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = member_relationships)]
+pub struct NewRelationship {
+    pub guardian_id: Uuid,
+    pub dependent_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DependentRequest {
+    pub dependent_id: Uuid,
+}
+
+pub fn add_dependent(guardian_id: Uuid, dependent_id: Uuid, conn: &mut PgConnection) -> Result<()> {
+    if is_guardian_of(dependent_id, guardian_id, conn)? {
+        return Err(LibError::ActixError(
+            ErrorBadRequest(format!(
+                "member {dependent_id} is a guardian of {guardian_id}"
+            ))
+            .to_string(),
+        )
+        .into());
+    }
+
+    conn.transaction(|conn| {
+        diesel::insert_into(member_relationships::table)
+            .values(&NewRelationship {
+                guardian_id,
+                dependent_id,
+            })
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        // stays a dependent when unlinked again, the member may have no email
+        diesel::update(members::table.find(dependent_id))
+            .set(members::dependent.eq(true))
+            .execute(conn)?;
+        Ok(())
+    })
+}
+
+pub fn remove_dependent(
+    guardian_id: Uuid,
+    dependent_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<bool> {
+    let num_deleted = diesel::delete(
+        member_relationships::table
+            .filter(member_relationships::guardian_id.eq(guardian_id))
+            .filter(member_relationships::dependent_id.eq(dependent_id)),
+    )
+    .execute(conn)?;
+    Ok(num_deleted > 0)
+}
+
+pub fn get_dependents(guardian_id: Uuid, conn: &mut PgConnection) -> Result<Vec<Member>> {
+    Ok(members::table
+        .filter(
+            members::member_id.eq_any(
+                member_relationships::table
+                    .filter(member_relationships::guardian_id.eq(guardian_id))
+                    .select(member_relationships::dependent_id),
+            ),
+        )
+        .order(members::name)
+        .load(conn)?)
+}
+
+pub fn get_guardians(dependent_id: Uuid, conn: &mut PgConnection) -> Result<Vec<Member>> {
+    Ok(members::table
+        .filter(
+            members::member_id.eq_any(
+                member_relationships::table
+                    .filter(member_relationships::dependent_id.eq(dependent_id))
+                    .select(member_relationships::guardian_id),
+            ),
+        )
+        .order(members::name)
+        .load(conn)?)
+}
+
+pub fn is_guardian_of(
+    guardian_id: Uuid,
+    dependent_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<bool> {
+    let count: i64 = member_relationships::table
+        .filter(member_relationships::guardian_id.eq(guardian_id))
+        .filter(member_relationships::dependent_id.eq(dependent_id))
+        .count()
+        .get_result(conn)?;
+    Ok(count > 0)
+}
+
+/// Refuses a new loan for `member_id` when it would push any household the
+/// member belongs to over its borrowing cap.
+///
+/// A household is a guardian together with their dependents, and the cap is the
+/// guardian's `household_limit`. A member can belong to several households, one
+/// per guardian, and every one of them is checked.
+///
+/// Meant to run inside the checkout transaction: the head of every household
+/// is locked, and every checkout in a household goes through that lock, so two
+/// members borrowing at once cannot both fit under the cap. Locking the heads
+/// rather than every member keeps dependents that already locked themselves
+/// from deadlocking.
+pub fn check_household_limit(member_id: Uuid, conn: &mut PgConnection) -> Result<()> {
+    let mut heads: Vec<Uuid> = member_relationships::table
+        .filter(member_relationships::dependent_id.eq(member_id))
+        .select(member_relationships::guardian_id)
+        .load(conn)?;
+    heads.push(member_id);
+
+    members::table
+        .filter(members::member_id.eq_any(&heads))
+        .order(members::member_id)
+        .select(members::member_id)
+        .for_update()
+        .load::<Uuid>(conn)?;
+
+    let capped: Vec<(Uuid, Option<i32>)> = members::table
+        .filter(members::member_id.eq_any(&heads))
+        .filter(members::household_limit.is_not_null())
+        .select((members::member_id, members::household_limit))
+        .load(conn)?;
+
+    for (head, limit) in capped {
+        let Some(limit) = limit else { continue };
+        let borrowed: Option<i64> = members::table
+            .filter(
+                members::member_id.eq(head).or(members::member_id.eq_any(
+                    member_relationships::table
+                        .filter(member_relationships::guardian_id.eq(head))
+                        .select(member_relationships::dependent_id),
+                )),
+            )
+            .select(diesel::dsl::sum(members::borrowed))
+            .get_result(conn)?;
+
+        if borrowed.unwrap_or(0) >= i64::from(limit) {
+            return Err(LibError::ActixError(
+                ErrorBadRequest(format!("household borrowing limit of {limit} reached"))
+                    .to_string(),
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,3 @@
+pub mod handlers;
+pub mod iso18626;
+pub mod models;
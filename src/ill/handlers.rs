@@ -0,0 +1,165 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+    errors::error_response,
+    pagination::Pagination,
+};
+use actix_web::{get, post, web, HttpResponse, Responder};
+use uuid::Uuid;
+
+use super::iso18626::AgencyId;
+use super::models::{
+    cancel_request, get_ill_request, get_messages, get_queue, handle_message, receive_item,
+    return_item, send_request, submit_request, update_lending, IllQueueFilter, LendingUpdate,
+    NewIllRequest, SendRequest,
+};
+
+/// A member asking us to borrow a book from a partner library.
+#[post("/ill/requests")]
+async fn add_request(principal: Principal, payload: web::Json<NewIllRequest>) -> impl Responder {
+    if let Err(denied) = principal.require_for(payload.member_id, Permission::RequestIll) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match submit_request(&payload, &mut conn) {
+        Ok(id) => HttpResponse::Ok().json(id),
+        Err(e) => error_response(&e),
+    }
+}
+
+#[get("/ill/requests/{ill_request_id}")]
+async fn fetch_request(principal: Principal, id: web::Path<Uuid>) -> impl Responder {
+    let mut conn = establish_connection();
+    match get_ill_request(*id, &mut conn) {
+        Ok(Some(request)) => match request.member_id.map_or_else(
+            || principal.require(Permission::ManageIll),
+            |member_id| principal.require_for(member_id, Permission::RequestIll),
+        ) {
+            Ok(()) => HttpResponse::Ok().json(request),
+            Err(denied) => denied,
+        },
+        Ok(None) => {
+            HttpResponse::NotFound().json(format!("interlibrary loan request {id} not found"))
+        }
+        Err(e) => error_response(&e),
+    }
+}
+
+/// The staff queue of borrowing and lending requests, oldest first.
+#[get("/admin/ill/requests")]
+async fn fetch_queue(
+    principal: Principal,
+    filter: web::Query<IllQueueFilter>,
+    pagination: web::Query<Pagination>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageIll) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_queue(&filter, *pagination, &mut conn) {
+        Ok(requests) => HttpResponse::Ok().json(requests),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// The ISO 18626 messages exchanged about a request. Outbound ones are for the
+/// partner integration to deliver.
+#[get("/admin/ill/requests/{ill_request_id}/messages")]
+async fn fetch_messages(principal: Principal, id: web::Path<Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageIll) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_messages(*id, &mut conn) {
+        Ok(messages) => HttpResponse::Ok().json(messages),
+        Err(e) => error_response(&e),
+    }
+}
+
+#[post("/admin/ill/requests/{ill_request_id}/send")]
+async fn send(
+    principal: Principal,
+    id: web::Path<Uuid>,
+    payload: web::Json<SendRequest>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageIll) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match send_request(*id, &payload.supplying_agency, &mut conn) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Books in the copy a partner sent us and returns its book id, which the
+/// member who asked for it can then borrow like any other book.
+#[post("/admin/ill/requests/{ill_request_id}/receive")]
+async fn receive(principal: Principal, id: web::Path<Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageIll) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match receive_item(*id, &mut conn) {
+        Ok(book_id) => HttpResponse::Ok().json(book_id),
+        Err(e) => error_response(&e),
+    }
+}
+
+#[post("/admin/ill/requests/{ill_request_id}/return")]
+async fn ship_return(principal: Principal, id: web::Path<Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageIll) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match return_item(*id, &mut conn) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => error_response(&e),
+    }
+}
+
+#[post("/admin/ill/requests/{ill_request_id}/cancel")]
+async fn cancel(principal: Principal, id: web::Path<Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageIll) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match cancel_request(*id, &mut conn) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Answers a partner borrowing one of our books.
+#[post("/admin/ill/requests/{ill_request_id}/status")]
+async fn change_status(
+    principal: Principal,
+    id: web::Path<Uuid>,
+    payload: web::Json<LendingUpdate>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageIll) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match update_lending(*id, &payload, &mut conn) {
+        Ok(()) => HttpResponse::Ok().finish(),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Where partner libraries send their ISO 18626 messages. Every message is
+/// answered with a confirmation, which carries the error when it was refused.
+#[post("/ill/iso18626")]
+async fn exchange(principal: Principal, body: String) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ExchangeIll) {
+        return denied;
+    }
+    let Some(partner) = principal.agency.as_deref().map(AgencyId::parse) else {
+        return HttpResponse::Forbidden().json("credentials are not bound to a partner agency");
+    };
+    let mut conn = establish_connection();
+    match handle_message(&body, &partner, &mut conn).to_xml() {
+        Ok(xml) => HttpResponse::Ok().content_type("application/xml").body(xml),
+        Err(e) => error_response(&e),
+    }
+}
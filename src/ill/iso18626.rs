@@ -0,0 +1,392 @@
+//! The subset of ISO 18626 messages exchanged with partner libraries: loan
+//! requests, status changes from the supplying agency, actions from the
+//! requesting agency and the confirmations answering each of them.
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDate, SecondsFormat};
+use serde::{Deserialize, Serialize};
+
+pub const NAMESPACE: &str = "http://illtransactions.org/2013/iso18626";
+pub const VERSION: &str = "1.2";
+
+fn namespace() -> String {
+    NAMESPACE.to_string()
+}
+
+fn version() -> String {
+    VERSION.to_string()
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename = "ISO18626Message", rename_all = "camelCase")]
+pub struct Message {
+    #[serde(rename = "@xmlns", skip_deserializing, default = "namespace")]
+    pub xmlns: String,
+    #[serde(rename = "@xmlns:ill", skip_deserializing, default = "namespace")]
+    pub xmlns_ill: String,
+    #[serde(rename = "@ill:version", skip_deserializing, default = "version")]
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<Request>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_confirmation: Option<Confirmation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supplying_agency_message: Option<SupplyingAgencyMessage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supplying_agency_message_confirmation: Option<Confirmation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requesting_agency_message: Option<RequestingAgencyMessage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requesting_agency_message_confirmation: Option<Confirmation>,
+}
+
+impl Message {
+    pub fn new() -> Self {
+        Message {
+            xmlns: namespace(),
+            xmlns_ill: namespace(),
+            version: version(),
+            ..Default::default()
+        }
+    }
+
+    pub fn parse(xml: &str) -> Result<Self> {
+        Ok(quick_xml::de::from_str(xml)?)
+    }
+
+    pub fn to_xml(&self) -> Result<String> {
+        Ok(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>{}",
+            quick_xml::se::to_string(self)?
+        ))
+    }
+}
+
+/// An agency written as `type:value` elsewhere, e.g. `ISIL:DK-710100`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgencyId {
+    pub agency_id_type: String,
+    pub agency_id_value: String,
+}
+
+impl AgencyId {
+    /// Reads `type:value`, taking an agency without a type as an ISIL.
+    pub fn parse(agency: &str) -> Self {
+        match agency.split_once(':') {
+            Some((agency_id_type, agency_id_value)) => AgencyId {
+                agency_id_type: agency_id_type.trim().to_string(),
+                agency_id_value: agency_id_value.trim().to_string(),
+            },
+            None => AgencyId {
+                agency_id_type: "ISIL".to_string(),
+                agency_id_value: agency.trim().to_string(),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for AgencyId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.agency_id_type, self.agency_id_value)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Header {
+    pub supplying_agency_id: AgencyId,
+    pub requesting_agency_id: AgencyId,
+    pub timestamp: String,
+    pub requesting_agency_request_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supplying_agency_request_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    pub header: Header,
+    pub bibliographic_info: BibliographicInfo,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_info: Option<ServiceInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BibliographicInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub bibliographic_item_id: Vec<BibliographicItemId>,
+}
+
+impl BibliographicInfo {
+    pub fn isbn(&self) -> Option<&str> {
+        self.bibliographic_item_id
+            .iter()
+            .find(|id| {
+                id.bibliographic_item_identifier_code
+                    .eq_ignore_ascii_case("ISBN")
+            })
+            .map(|id| id.bibliographic_item_identifier.as_str())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BibliographicItemId {
+    pub bibliographic_item_identifier: String,
+    pub bibliographic_item_identifier_code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceInfo {
+    pub service_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupplyingAgencyMessage {
+    pub header: Header,
+    pub message_info: MessageInfo,
+    pub status_info: StatusInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageInfo {
+    pub reason_for_message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusInfo {
+    pub status: String,
+    pub last_change: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due_date: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestingAgencyMessage {
+    pub header: Header,
+    pub action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Confirmation {
+    pub confirmation_header: ConfirmationHeader,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_data: Option<ErrorData>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfirmationHeader {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supplying_agency_id: Option<AgencyId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requesting_agency_id: Option<AgencyId>,
+    pub timestamp: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requesting_agency_request_id: Option<String>,
+    pub timestamp_received: String,
+    pub message_status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorData {
+    pub error_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_value: Option<String>,
+}
+
+/// Why a message was refused, as the `errorType` of its confirmation.
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorType {
+    UnsupportedActionType,
+    UnsupportedReasonForMessageType,
+    UnrecognisedDataElement,
+    UnrecognisedDataValue,
+    BadlyFormedMessage,
+}
+
+impl ErrorType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorType::UnsupportedActionType => "UnsupportedActionType",
+            ErrorType::UnsupportedReasonForMessageType => "UnsupportedReasonForMessageType",
+            ErrorType::UnrecognisedDataElement => "UnrecognisedDataElement",
+            ErrorType::UnrecognisedDataValue => "UnrecognisedDataValue",
+            ErrorType::BadlyFormedMessage => "BadlyFormedMessage",
+        }
+    }
+}
+
+impl Confirmation {
+    /// Answers `header`, received at `received`, with `OK` or with `error`.
+    pub fn new(
+        header: Option<&Header>,
+        received: &str,
+        error: Option<(ErrorType, String)>,
+    ) -> Self {
+        Confirmation {
+            confirmation_header: ConfirmationHeader {
+                supplying_agency_id: header.map(|h| h.supplying_agency_id.clone()),
+                requesting_agency_id: header.map(|h| h.requesting_agency_id.clone()),
+                timestamp: timestamp(),
+                requesting_agency_request_id: header
+                    .map(|h| h.requesting_agency_request_id.clone()),
+                timestamp_received: received.to_string(),
+                message_status: if error.is_some() { "ERROR" } else { "OK" }.to_string(),
+            },
+            error_data: error.map(|(error_type, error_value)| ErrorData {
+                error_type: error_type.as_str().to_string(),
+                error_value: Some(error_value),
+            }),
+        }
+    }
+}
+
+/// Now, the way ISO 18626 timestamps are written.
+pub fn timestamp() -> String {
+    chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Reads a `dueDate`, which partners send either as a full timestamp or as a
+/// plain date.
+pub fn parse_date(value: &str) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|date| date.date_naive())
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d").ok())
+}
+
+pub fn format_date(date: NaiveDate) -> String {
+    format!("{date}T00:00:00Z")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ISO18626Message xmlns="http://illtransactions.org/2013/iso18626" ill:version="1.2">
+  <request>
+    <header>
+      <supplyingAgencyId>
+        <agencyIdType>ISIL</agencyIdType>
+        <agencyIdValue>US-LIB</agencyIdValue>
+      </supplyingAgencyId>
+      <requestingAgencyId>
+        <agencyIdType>ISIL</agencyIdType>
+        <agencyIdValue>DK-710100</agencyIdValue>
+      </requestingAgencyId>
+      <timestamp>2023-06-10T09:00:00Z</timestamp>
+      <requestingAgencyRequestId>req-1</requestingAgencyRequestId>
+    </header>
+    <bibliographicInfo>
+      <title>Dune</title>
+      <bibliographicItemId>
+        <bibliographicItemIdentifier>9780441013593</bibliographicItemIdentifier>
+        <bibliographicItemIdentifierCode>isbn</bibliographicItemIdentifierCode>
+      </bibliographicItemId>
+    </bibliographicInfo>
+  </request>
+</ISO18626Message>"#;
+
+    #[test]
+    fn requests_are_parsed() {
+        let message = Message::parse(REQUEST).unwrap();
+        let request = message.request.unwrap();
+        assert_eq!(
+            request.header.requesting_agency_id,
+            AgencyId::parse("ISIL:DK-710100")
+        );
+        assert_eq!(request.header.requesting_agency_request_id, "req-1");
+        assert_eq!(request.bibliographic_info.title.as_deref(), Some("Dune"));
+        assert_eq!(request.bibliographic_info.isbn(), Some("9780441013593"));
+        assert!(request.service_info.is_none());
+        assert!(message.supplying_agency_message.is_none());
+    }
+
+    #[test]
+    fn garbage_is_not_a_message() {
+        assert!(Message::parse("not xml").is_err());
+        assert!(Message::parse("<ISO18626Message><request/></ISO18626Message>").is_err());
+    }
+
+    #[test]
+    fn messages_survive_a_round_trip() {
+        let mut message = Message::new();
+        message.request = Message::parse(REQUEST).unwrap().request;
+        let xml = message.to_xml().unwrap();
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?><ISO18626Message"));
+        assert!(xml.contains(&format!("xmlns=\"{NAMESPACE}\"")));
+        assert!(xml.contains(&format!("ill:version=\"{VERSION}\"")));
+
+        let request = Message::parse(&xml).unwrap().request.unwrap();
+        assert_eq!(
+            request.header.supplying_agency_id,
+            AgencyId::parse("US-LIB")
+        );
+        assert_eq!(request.bibliographic_info.isbn(), Some("9780441013593"));
+    }
+
+    #[test]
+    fn confirmations_carry_the_error() {
+        let header = Message::parse(REQUEST).unwrap().request.unwrap().header;
+        let mut message = Message::new();
+        message.request_confirmation = Some(Confirmation::new(
+            Some(&header),
+            "2023-06-10T09:00:01Z",
+            Some((ErrorType::UnrecognisedDataValue, "no such book".to_string())),
+        ));
+        let xml = message.to_xml().unwrap();
+        assert!(xml.contains("<messageStatus>ERROR</messageStatus>"));
+        assert!(xml.contains("<errorType>UnrecognisedDataValue</errorType>"));
+        assert!(xml.contains("<requestingAgencyRequestId>req-1</requestingAgencyRequestId>"));
+
+        let ok = Confirmation::new(None, "2023-06-10T09:00:01Z", None);
+        assert_eq!(ok.confirmation_header.message_status, "OK");
+        assert!(ok.error_data.is_none());
+    }
+
+    #[test]
+    fn agencies_default_to_isil() {
+        assert_eq!(
+            AgencyId::parse(" DK-710100 "),
+            AgencyId {
+                agency_id_type: "ISIL".to_string(),
+                agency_id_value: "DK-710100".to_string(),
+            }
+        );
+        let agency = AgencyId::parse("OCLC: 12345");
+        assert_eq!(agency.agency_id_type, "OCLC");
+        assert_eq!(agency.agency_id_value, "12345");
+        assert_eq!(agency.to_string(), "OCLC:12345");
+    }
+
+    #[test]
+    fn due_dates_are_read_as_timestamps_or_dates() {
+        let date = NaiveDate::from_ymd_opt(2023, 7, 1).unwrap();
+        assert_eq!(parse_date("2023-07-01T00:00:00Z"), Some(date));
+        assert_eq!(parse_date("2023-07-01T23:30:00-02:00"), Some(date));
+        assert_eq!(parse_date(" 2023-07-01 "), Some(date));
+        assert_eq!(parse_date("01/07/2023"), None);
+        assert_eq!(parse_date(&format_date(date)), Some(date));
+    }
+}
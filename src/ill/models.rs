@@ -0,0 +1,1036 @@
+use std::io::Write;
+
+use actix_web::error::ErrorBadRequest;
+use anyhow::Result;
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::{
+    deserialize::FromSql,
+    pg::{Pg, PgValue},
+    prelude::Queryable,
+    serialize::{IsNull, ToSql},
+    AsExpression, Connection, ExpressionMethods, FromSqlRow, OptionalExtension, PgConnection,
+    QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    books::models::{update_book_status, DEFAULT_BRANCH},
+    errors::LibError,
+    holds::models::advance_queue,
+    loans::models::LoanStatus,
+    members::models::get_member,
+    pagination::Pagination,
+    schema::{books, ill_messages, ill_requests, loans},
+};
+
+use super::iso18626::{
+    format_date, parse_date, timestamp, AgencyId, BibliographicInfo, BibliographicItemId,
+    Confirmation, ErrorType, Header, Message, MessageInfo, Request, RequestingAgencyMessage,
+    ServiceInfo, StatusInfo, SupplyingAgencyMessage,
+};
+
+/// The agency we are known as to partners, unless `ILL_AGENCY_ID` says
+/// otherwise.
+pub const DEFAULT_AGENCY_ID: &str = "ISIL:XX-LIBSTACK";
+
+/// Item type of the copies received from partners, see [`receive_item`].
+pub const ILL_ITEM_TYPE: &str = "ill";
+
+pub fn agency_id() -> AgencyId {
+    AgencyId::parse(
+        &std::env::var("ILL_AGENCY_ID").unwrap_or_else(|_| DEFAULT_AGENCY_ID.to_string()),
+    )
+}
+
+#[derive(Debug, PartialEq, FromSqlRow, AsExpression, Eq, Serialize, Deserialize, Clone, Copy)]
+#[diesel(sql_type = crate::schema::sql_types::IllRole)]
+#[serde(rename_all = "snake_case")]
+pub enum IllRole {
+    /// We borrow from a partner for one of our members.
+    Borrowing,
+    /// A partner borrows one of our books.
+    Lending,
+}
+
+impl ToSql<crate::schema::sql_types::IllRole, Pg> for IllRole {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        match *self {
+            IllRole::Borrowing => out.write_all(b"borrowing")?,
+            IllRole::Lending => out.write_all(b"lending")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<crate::schema::sql_types::IllRole, Pg> for IllRole {
+    fn from_sql(bytes: PgValue<'_>) -> diesel::deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"borrowing" => Ok(IllRole::Borrowing),
+            b"lending" => Ok(IllRole::Lending),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+/// The ISO 18626 status of a request, plus [`IllStatus::Submitted`] and
+/// [`IllStatus::Requested`] for borrowing requests that were not sent to or
+/// not answered by a partner yet.
+#[derive(Debug, PartialEq, FromSqlRow, AsExpression, Eq, Serialize, Deserialize, Clone, Copy)]
+#[diesel(sql_type = crate::schema::sql_types::IllStatus)]
+#[serde(rename_all = "snake_case")]
+pub enum IllStatus {
+    Submitted,
+    Requested,
+    RequestReceived,
+    ExpectToSupply,
+    WillSupply,
+    Loaned,
+    Overdue,
+    Recalled,
+    RetryPossible,
+    Unfilled,
+    LoanCompleted,
+    Cancelled,
+}
+
+impl IllStatus {
+    /// The name of the status in ISO 18626 messages, for those it has one.
+    pub fn iso_name(self) -> Option<&'static str> {
+        Some(match self {
+            IllStatus::Submitted | IllStatus::Requested => return None,
+            IllStatus::RequestReceived => "RequestReceived",
+            IllStatus::ExpectToSupply => "ExpectToSupply",
+            IllStatus::WillSupply => "WillSupply",
+            IllStatus::Loaned => "Loaned",
+            IllStatus::Overdue => "Overdue",
+            IllStatus::Recalled => "Recalled",
+            IllStatus::RetryPossible => "RetryPossible",
+            IllStatus::Unfilled => "Unfilled",
+            IllStatus::LoanCompleted => "LoanCompleted",
+            IllStatus::Cancelled => "Cancelled",
+        })
+    }
+
+    pub fn from_iso_name(name: &str) -> Option<Self> {
+        [
+            IllStatus::RequestReceived,
+            IllStatus::ExpectToSupply,
+            IllStatus::WillSupply,
+            IllStatus::Loaned,
+            IllStatus::Overdue,
+            IllStatus::Recalled,
+            IllStatus::RetryPossible,
+            IllStatus::Unfilled,
+            IllStatus::LoanCompleted,
+            IllStatus::Cancelled,
+        ]
+        .into_iter()
+        .find(|status| status.iso_name() == Some(name.trim()))
+    }
+
+    /// Whether the partner has the copy, or we have the partner's.
+    fn on_loan(self) -> bool {
+        matches!(
+            self,
+            IllStatus::Loaned | IllStatus::Overdue | IllStatus::Recalled
+        )
+    }
+}
+
+impl ToSql<crate::schema::sql_types::IllStatus, Pg> for IllStatus {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        match *self {
+            IllStatus::Submitted => out.write_all(b"submitted")?,
+            IllStatus::Requested => out.write_all(b"requested")?,
+            IllStatus::RequestReceived => out.write_all(b"request_received")?,
+            IllStatus::ExpectToSupply => out.write_all(b"expect_to_supply")?,
+            IllStatus::WillSupply => out.write_all(b"will_supply")?,
+            IllStatus::Loaned => out.write_all(b"loaned")?,
+            IllStatus::Overdue => out.write_all(b"overdue")?,
+            IllStatus::Recalled => out.write_all(b"recalled")?,
+            IllStatus::RetryPossible => out.write_all(b"retry_possible")?,
+            IllStatus::Unfilled => out.write_all(b"unfilled")?,
+            IllStatus::LoanCompleted => out.write_all(b"loan_completed")?,
+            IllStatus::Cancelled => out.write_all(b"cancelled")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<crate::schema::sql_types::IllStatus, Pg> for IllStatus {
+    fn from_sql(bytes: PgValue<'_>) -> diesel::deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"submitted" => Ok(IllStatus::Submitted),
+            b"requested" => Ok(IllStatus::Requested),
+            b"request_received" => Ok(IllStatus::RequestReceived),
+            b"expect_to_supply" => Ok(IllStatus::ExpectToSupply),
+            b"will_supply" => Ok(IllStatus::WillSupply),
+            b"loaned" => Ok(IllStatus::Loaned),
+            b"overdue" => Ok(IllStatus::Overdue),
+            b"recalled" => Ok(IllStatus::Recalled),
+            b"retry_possible" => Ok(IllStatus::RetryPossible),
+            b"unfilled" => Ok(IllStatus::Unfilled),
+            b"loan_completed" => Ok(IllStatus::LoanCompleted),
+            b"cancelled" => Ok(IllStatus::Cancelled),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = ill_requests)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct IllRequest {
+    pub ill_request_id: Uuid,
+    pub role: IllRole,
+    pub requesting_agency: String,
+    pub supplying_agency: Option<String>,
+    pub requesting_request_id: String,
+    pub member_id: Option<Uuid>,
+    pub book_id: Option<Uuid>,
+    pub title: String,
+    pub author: Option<String>,
+    pub isbn: Option<String>,
+    pub status: IllStatus,
+    pub due_date: Option<NaiveDate>,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = ill_messages)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct IllMessage {
+    pub message_id: Uuid,
+    pub ill_request_id: Uuid,
+    pub inbound: bool,
+    pub body: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// A member asking us to borrow a book we do not own.
+#[derive(Debug, Deserialize)]
+pub struct NewIllRequest {
+    pub member_id: Uuid,
+    pub title: String,
+    pub author: Option<String>,
+    pub isbn: Option<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SendRequest {
+    /// The partner to borrow from, as `type:value`.
+    pub supplying_agency: String,
+}
+
+/// Our answer to a partner borrowing from us.
+#[derive(Debug, Deserialize)]
+pub struct LendingUpdate {
+    pub status: IllStatus,
+    /// Required when the book goes out.
+    pub due_date: Option<NaiveDate>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IllQueueFilter {
+    pub role: Option<IllRole>,
+    pub status: Option<IllStatus>,
+}
+
+fn bad_request(message: String) -> anyhow::Error {
+    LibError::ActixError(ErrorBadRequest(message).to_string()).into()
+}
+
+pub fn submit_request(request: &NewIllRequest, conn: &mut PgConnection) -> Result<Uuid> {
+    let member = get_member(request.member_id, conn)?
+        .ok_or_else(|| LibError::DbError(format!("member {} not found", request.member_id)))?;
+    if member.anonymized_at.is_some() {
+        return Err(bad_request(
+            "Member account has been anonymized".to_string(),
+        ));
+    }
+    let id = Uuid::new_v4();
+    Ok(diesel::insert_into(ill_requests::table)
+        .values((
+            ill_requests::ill_request_id.eq(id),
+            ill_requests::role.eq(IllRole::Borrowing),
+            ill_requests::requesting_agency.eq(agency_id().to_string()),
+            ill_requests::requesting_request_id.eq(id.to_string()),
+            ill_requests::member_id.eq(request.member_id),
+            ill_requests::title.eq(&request.title),
+            ill_requests::author.eq(&request.author),
+            ill_requests::isbn.eq(&request.isbn),
+            ill_requests::status.eq(IllStatus::Submitted),
+            ill_requests::note.eq(&request.note),
+        ))
+        .returning(ill_requests::ill_request_id)
+        .get_result(conn)?)
+}
+
+pub fn get_ill_request(id: Uuid, conn: &mut PgConnection) -> Result<Option<IllRequest>> {
+    Ok(ill_requests::table
+        .find(id)
+        .select(IllRequest::as_select())
+        .first(conn)
+        .optional()?)
+}
+
+/// The borrowing requests a member made, oldest first.
+pub fn get_member_requests(member_id: Uuid, conn: &mut PgConnection) -> Result<Vec<IllRequest>> {
+    Ok(ill_requests::table
+        .filter(ill_requests::member_id.eq(member_id))
+        .order(ill_requests::created_at.asc())
+        .select(IllRequest::as_select())
+        .load(conn)?)
+}
+
+/// The staff queue, oldest first.
+pub fn get_queue(
+    filter: &IllQueueFilter,
+    pagination: Pagination,
+    conn: &mut PgConnection,
+) -> Result<Vec<IllRequest>> {
+    let mut query = ill_requests::table
+        .select(IllRequest::as_select())
+        .order(ill_requests::created_at.asc())
+        .into_boxed();
+    if let Some(role) = filter.role {
+        query = query.filter(ill_requests::role.eq(role));
+    }
+    if let Some(status) = filter.status {
+        query = query.filter(ill_requests::status.eq(status));
+    }
+    Ok(query
+        .limit(pagination.per_page())
+        .offset(pagination.offset())
+        .load(conn)?)
+}
+
+/// The messages exchanged about a request, oldest first.
+pub fn get_messages(id: Uuid, conn: &mut PgConnection) -> Result<Vec<IllMessage>> {
+    Ok(ill_messages::table
+        .filter(ill_messages::ill_request_id.eq(id))
+        .order(ill_messages::created_at.asc())
+        .select(IllMessage::as_select())
+        .load(conn)?)
+}
+
+/// The borrowing request a copy received from a partner belongs to, while the
+/// copy is with us. Loans of such a copy go to the member who asked for it and
+/// are due back before the partner wants it.
+pub fn get_borrowed_copy(book_id: Uuid, conn: &mut PgConnection) -> Result<Option<IllRequest>> {
+    Ok(ill_requests::table
+        .filter(ill_requests::role.eq(IllRole::Borrowing))
+        .filter(ill_requests::book_id.eq(book_id))
+        .filter(ill_requests::status.eq_any([
+            IllStatus::Loaned,
+            IllStatus::Overdue,
+            IllStatus::Recalled,
+        ]))
+        .select(IllRequest::as_select())
+        .first(conn)
+        .optional()?)
+}
+
+fn lock_request(id: Uuid, role: IllRole, conn: &mut PgConnection) -> Result<IllRequest> {
+    let request: IllRequest = ill_requests::table
+        .find(id)
+        .for_update()
+        .select(IllRequest::as_select())
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| LibError::DbError(format!("interlibrary loan request {id} not found")))?;
+    if request.role != role {
+        return Err(bad_request(format!(
+            "interlibrary loan request {id} is not a {} request",
+            if role == IllRole::Borrowing {
+                "borrowing"
+            } else {
+                "lending"
+            }
+        )));
+    }
+    Ok(request)
+}
+
+fn set_status(
+    id: Uuid,
+    status: IllStatus,
+    due_date: Option<NaiveDate>,
+    note: Option<&str>,
+    conn: &mut PgConnection,
+) -> Result<()> {
+    diesel::update(ill_requests::table.find(id))
+        .set((
+            ill_requests::status.eq(status),
+            ill_requests::updated_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(conn)?;
+    if let Some(due_date) = due_date {
+        diesel::update(ill_requests::table.find(id))
+            .set(ill_requests::due_date.eq(due_date))
+            .execute(conn)?;
+    }
+    if let Some(note) = note {
+        diesel::update(ill_requests::table.find(id))
+            .set(ill_requests::note.eq(note))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+fn log_message(id: Uuid, inbound: bool, body: &str, conn: &mut PgConnection) -> Result<()> {
+    diesel::insert_into(ill_messages::table)
+        .values((
+            ill_messages::ill_request_id.eq(id),
+            ill_messages::inbound.eq(inbound),
+            ill_messages::body.eq(body),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+fn header(request: &IllRequest) -> Header {
+    Header {
+        supplying_agency_id: AgencyId::parse(request.supplying_agency.as_deref().unwrap_or("")),
+        requesting_agency_id: AgencyId::parse(&request.requesting_agency),
+        timestamp: timestamp(),
+        requesting_agency_request_id: request.requesting_request_id.clone(),
+        supplying_agency_request_id: None,
+    }
+}
+
+/// Queues an outbound `requestingAgencyMessage` for a borrowing request.
+fn send_action(
+    request: &IllRequest,
+    action: &str,
+    note: Option<String>,
+    conn: &mut PgConnection,
+) -> Result<()> {
+    let mut message = Message::new();
+    message.requesting_agency_message = Some(RequestingAgencyMessage {
+        header: header(request),
+        action: action.to_string(),
+        note,
+    });
+    log_message(request.ill_request_id, false, &message.to_xml()?, conn)
+}
+
+/// Sends a borrowing request to `supplying_agency`. A request a partner could
+/// not fill can be sent again to another one.
+pub fn send_request(id: Uuid, supplying_agency: &str, conn: &mut PgConnection) -> Result<()> {
+    conn.transaction(|conn| {
+        let mut request = lock_request(id, IllRole::Borrowing, conn)?;
+        if !matches!(
+            request.status,
+            IllStatus::Submitted | IllStatus::RetryPossible | IllStatus::Unfilled
+        ) {
+            return Err(bad_request(format!(
+                "interlibrary loan request {id} was already sent"
+            )));
+        }
+        let supplying_agency = AgencyId::parse(supplying_agency).to_string();
+        diesel::update(ill_requests::table.find(id))
+            .set(ill_requests::supplying_agency.eq(&supplying_agency))
+            .execute(conn)?;
+        request.supplying_agency = Some(supplying_agency);
+
+        let mut message = Message::new();
+        message.request = Some(Request {
+            header: header(&request),
+            bibliographic_info: BibliographicInfo {
+                title: Some(request.title.clone()),
+                author: request.author.clone(),
+                bibliographic_item_id: request
+                    .isbn
+                    .iter()
+                    .map(|isbn| BibliographicItemId {
+                        bibliographic_item_identifier: isbn.clone(),
+                        bibliographic_item_identifier_code: "ISBN".to_string(),
+                    })
+                    .collect(),
+            },
+            service_info: Some(ServiceInfo {
+                service_type: "Loan".to_string(),
+                note: request.note.clone(),
+            }),
+        });
+        log_message(id, false, &message.to_xml()?, conn)?;
+        set_status(id, IllStatus::Requested, None, None, conn)
+    })
+}
+
+/// Books in the copy a partner lent us, so it can be checked out to the member
+/// who asked for it. Returns the id of the new book.
+pub fn receive_item(id: Uuid, conn: &mut PgConnection) -> Result<Uuid> {
+    conn.transaction(|conn| {
+        let request = lock_request(id, IllRole::Borrowing, conn)?;
+        if !request.status.on_loan() || request.book_id.is_some() {
+            return Err(bad_request(format!(
+                "interlibrary loan request {id} has no copy on its way"
+            )));
+        }
+        let book_id: Uuid = diesel::insert_into(books::table)
+            .values((
+                books::title.eq(&request.title),
+                books::author.eq(request.author.as_deref().unwrap_or("")),
+                books::publication_year.eq(0),
+                books::isbn.eq(&request.isbn),
+                books::availability_status.eq(true),
+                books::item_type.eq(ILL_ITEM_TYPE),
+                books::branch.eq(DEFAULT_BRANCH),
+            ))
+            .returning(books::book_id)
+            .get_result(conn)?;
+        diesel::update(ill_requests::table.find(id))
+            .set(ill_requests::book_id.eq(book_id))
+            .execute(conn)?;
+        send_action(&request, "Received", None, conn)?;
+        Ok(book_id)
+    })
+}
+
+/// Sends the copy a partner lent us back once the member returned it.
+pub fn return_item(id: Uuid, conn: &mut PgConnection) -> Result<()> {
+    conn.transaction(|conn| {
+        let request = lock_request(id, IllRole::Borrowing, conn)?;
+        let Some(book_id) = request.book_id.filter(|_| request.status.on_loan()) else {
+            return Err(bad_request(format!(
+                "interlibrary loan request {id} has no copy to return"
+            )));
+        };
+        let out: i64 = loans::table
+            .filter(loans::book_id.eq(book_id))
+            .filter(loans::status.eq_any([LoanStatus::Open, LoanStatus::Overdue]))
+            .count()
+            .get_result(conn)?;
+        if out > 0 {
+            return Err(bad_request(format!(
+                "the copy of interlibrary loan request {id} is still on loan"
+            )));
+        }
+        let on_shelf: bool = books::table
+            .find(book_id)
+            .select(books::availability_status)
+            .for_update()
+            .first(conn)?;
+        if !on_shelf {
+            return Err(bad_request(format!(
+                "the copy of interlibrary loan request {id} was already sent back"
+            )));
+        }
+        update_book_status(book_id, false, conn)?;
+        send_action(&request, "ShippedReturn", None, conn)
+    })
+}
+
+/// Withdraws a borrowing request that was not filled yet.
+pub fn cancel_request(id: Uuid, conn: &mut PgConnection) -> Result<()> {
+    conn.transaction(|conn| {
+        let request = lock_request(id, IllRole::Borrowing, conn)?;
+        match request.status {
+            IllStatus::Submitted => {}
+            IllStatus::Requested
+            | IllStatus::RequestReceived
+            | IllStatus::ExpectToSupply
+            | IllStatus::WillSupply
+            | IllStatus::RetryPossible => send_action(&request, "Cancel", None, conn)?,
+            _ => {
+                return Err(bad_request(format!(
+                    "interlibrary loan request {id} can no longer be cancelled"
+                )))
+            }
+        }
+        set_status(id, IllStatus::Cancelled, None, None, conn)
+    })
+}
+
+/// Answers a partner borrowing from us. Lending the book takes it off our
+/// shelf until the partner sends it back.
+pub fn update_lending(id: Uuid, update: &LendingUpdate, conn: &mut PgConnection) -> Result<()> {
+    conn.transaction(|conn| {
+        let request = lock_request(id, IllRole::Lending, conn)?;
+        let closed = matches!(
+            request.status,
+            IllStatus::Unfilled | IllStatus::Cancelled | IllStatus::LoanCompleted
+        );
+        let allowed = match update.status {
+            IllStatus::ExpectToSupply
+            | IllStatus::WillSupply
+            | IllStatus::RetryPossible
+            | IllStatus::Unfilled
+            | IllStatus::Loaned => !request.status.on_loan(),
+            IllStatus::Overdue | IllStatus::Recalled | IllStatus::LoanCompleted => {
+                request.status.on_loan()
+            }
+            _ => false,
+        };
+        if closed || !allowed {
+            return Err(bad_request(format!(
+                "interlibrary loan request {id} cannot go from {:?} to {:?}",
+                request.status, update.status
+            )));
+        }
+
+        let today = chrono::Utc::now().date_naive();
+        match update.status {
+            IllStatus::Loaned => {
+                let Some(book_id) = request.book_id else {
+                    return Err(bad_request(format!(
+                        "interlibrary loan request {id} is not matched to one of our books"
+                    )));
+                };
+                if update.due_date.is_none_or(|due_date| due_date <= today) {
+                    return Err(bad_request("a future due_date is required".to_string()));
+                }
+                let available: bool = books::table
+                    .find(book_id)
+                    .select(books::availability_status)
+                    .for_update()
+                    .first(conn)?;
+                if !available {
+                    return Err(bad_request(format!("book {book_id} is not available")));
+                }
+                update_book_status(book_id, false, conn)?;
+            }
+            IllStatus::LoanCompleted => {
+                if let Some(book_id) = request.book_id {
+                    advance_queue(book_id, today, conn)?;
+                }
+            }
+            _ => {}
+        }
+        set_status(
+            id,
+            update.status,
+            update.due_date,
+            update.note.as_deref(),
+            conn,
+        )?;
+
+        let mut message = Message::new();
+        message.supplying_agency_message = Some(SupplyingAgencyMessage {
+            header: header(&request),
+            message_info: MessageInfo {
+                reason_for_message: "StatusChange".to_string(),
+                note: update.note.clone(),
+            },
+            status_info: StatusInfo {
+                status: update.status.iso_name().unwrap_or_default().to_string(),
+                last_change: timestamp(),
+                due_date: update.due_date.or(request.due_date).map(format_date),
+            },
+        });
+        log_message(id, false, &message.to_xml()?, conn)
+    })
+}
+
+type Refusal = (ErrorType, String);
+
+/// What a partner lending to us may answer before it sent the book.
+const SUPPLIER_ANSWERS: &[IllStatus] = &[
+    IllStatus::RequestReceived,
+    IllStatus::ExpectToSupply,
+    IllStatus::WillSupply,
+    IllStatus::RetryPossible,
+    IllStatus::Unfilled,
+    IllStatus::Loaned,
+    IllStatus::Cancelled,
+];
+
+/// What a partner lending to us may report while we have the book.
+const SUPPLIER_LOAN_UPDATES: &[IllStatus] = &[
+    IllStatus::Loaned,
+    IllStatus::Overdue,
+    IllStatus::Recalled,
+    IllStatus::LoanCompleted,
+];
+
+/// The statuses a partner may move one of our borrowing requests to, by the
+/// status it is in. Requests not listed, such as finished ones, stay as they
+/// are.
+const SUPPLIER_TRANSITIONS: [(IllStatus, &[IllStatus]); 8] = [
+    (IllStatus::Requested, SUPPLIER_ANSWERS),
+    (IllStatus::RequestReceived, SUPPLIER_ANSWERS),
+    (IllStatus::ExpectToSupply, SUPPLIER_ANSWERS),
+    (IllStatus::WillSupply, SUPPLIER_ANSWERS),
+    (IllStatus::RetryPossible, SUPPLIER_ANSWERS),
+    (IllStatus::Loaned, SUPPLIER_LOAN_UPDATES),
+    (IllStatus::Overdue, SUPPLIER_LOAN_UPDATES),
+    (IllStatus::Recalled, SUPPLIER_LOAN_UPDATES),
+];
+
+fn supplier_may_move(from: IllStatus, to: IllStatus) -> bool {
+    SUPPLIER_TRANSITIONS
+        .iter()
+        .any(|&(status, next)| status == from && next.contains(&to))
+}
+
+/// The statuses a lending request has to be in for a partner borrowing from
+/// us to take an action on it.
+fn action_allowed(action: &str, status: IllStatus) -> bool {
+    match action {
+        "Cancel" => matches!(
+            status,
+            IllStatus::RequestReceived
+                | IllStatus::ExpectToSupply
+                | IllStatus::WillSupply
+                | IllStatus::RetryPossible
+        ),
+        "Received" | "ShippedReturn" => status.on_loan(),
+        _ => true,
+    }
+}
+
+/// Confirms a message we could not handle with an error, so the partner sends
+/// it again later. What went wrong is only logged.
+fn failed(e: &anyhow::Error) -> Refusal {
+    log::error!("interlibrary loan message failed: {e}");
+    (
+        ErrorType::UnrecognisedDataValue,
+        "the message could not be processed, please send it again".to_string(),
+    )
+}
+
+/// Refuses a message naming another agency than the partner that sent it.
+fn not_partner(element: &str, agency: &AgencyId) -> Refusal {
+    (
+        ErrorType::UnrecognisedDataValue,
+        format!("{element} {agency} is not the agency of the sender"),
+    )
+}
+
+/// The `reasonForMessage` values of a `supplyingAgencyMessage`.
+const SUPPLYING_REASONS: [&str; 6] = [
+    "RequestResponse",
+    "StatusRequestResponse",
+    "RenewResponse",
+    "CancelResponse",
+    "StatusChange",
+    "Notification",
+];
+
+/// Handles a message from `partner` and returns the confirmation to answer
+/// with. Messages that cannot be handled are confirmed with an error rather
+/// than failing, as ISO 18626 expects, including those we failed to store. A
+/// partner can only act for itself: it has to be the requesting agency of
+/// requests and actions and the supplying agency of status changes.
+pub fn handle_message(xml: &str, partner: &AgencyId, conn: &mut PgConnection) -> Message {
+    let received = timestamp();
+    let mut reply = Message::new();
+    let message = match Message::parse(xml) {
+        Ok(message) => message,
+        Err(e) => {
+            reply.request_confirmation = Some(Confirmation::new(
+                None,
+                &received,
+                Some((ErrorType::BadlyFormedMessage, e.to_string())),
+            ));
+            return reply;
+        }
+    };
+
+    if let Some(request) = &message.request {
+        let result = conn
+            .transaction(|conn| receive_request(request, partner, xml, conn))
+            .unwrap_or_else(|e| Err(failed(&e)));
+        reply.request_confirmation = Some(Confirmation::new(
+            Some(&request.header),
+            &received,
+            result.err(),
+        ));
+    } else if let Some(update) = &message.supplying_agency_message {
+        let result = conn
+            .transaction(|conn| receive_status(update, partner, xml, conn))
+            .unwrap_or_else(|e| Err(failed(&e)));
+        reply.supplying_agency_message_confirmation = Some(Confirmation::new(
+            Some(&update.header),
+            &received,
+            result.err(),
+        ));
+    } else if let Some(action) = &message.requesting_agency_message {
+        let result = conn
+            .transaction(|conn| receive_action(action, partner, xml, conn))
+            .unwrap_or_else(|e| Err(failed(&e)));
+        reply.requesting_agency_message_confirmation = Some(Confirmation::new(
+            Some(&action.header),
+            &received,
+            result.err(),
+        ));
+    } else {
+        reply.request_confirmation = Some(Confirmation::new(
+            None,
+            &received,
+            Some((
+                ErrorType::UnrecognisedDataElement,
+                "expected a request, supplyingAgencyMessage or requestingAgencyMessage".to_string(),
+            )),
+        ));
+    }
+    reply
+}
+
+/// A partner asking to borrow from us. Sending the same request twice is not
+/// an error, so partners can retry.
+fn receive_request(
+    request: &Request,
+    partner: &AgencyId,
+    xml: &str,
+    conn: &mut PgConnection,
+) -> Result<std::result::Result<(), Refusal>> {
+    let header = &request.header;
+    if &header.requesting_agency_id != partner {
+        return Ok(Err(not_partner(
+            "requestingAgencyId",
+            &header.requesting_agency_id,
+        )));
+    }
+    if header.supplying_agency_id != agency_id() {
+        return Ok(Err((
+            ErrorType::UnrecognisedDataValue,
+            format!("supplyingAgencyId {}", header.supplying_agency_id),
+        )));
+    }
+    let Some(title) = request.bibliographic_info.title.clone() else {
+        return Ok(Err((
+            ErrorType::UnrecognisedDataElement,
+            "bibliographicInfo without a title".to_string(),
+        )));
+    };
+    let requesting_agency = header.requesting_agency_id.to_string();
+    let existing: Option<Uuid> = ill_requests::table
+        .filter(ill_requests::requesting_agency.eq(&requesting_agency))
+        .filter(ill_requests::requesting_request_id.eq(&header.requesting_agency_request_id))
+        .select(ill_requests::ill_request_id)
+        .first(conn)
+        .optional()?;
+    if existing.is_some() {
+        return Ok(Ok(()));
+    }
+
+    let isbn = request.bibliographic_info.isbn();
+    let book_id: Option<Uuid> = match isbn {
+        Some(isbn) => books::table
+            .filter(books::isbn.eq(isbn))
+            .filter(books::item_type.ne(ILL_ITEM_TYPE))
+            .select(books::book_id)
+            .first(conn)
+            .optional()?,
+        None => None,
+    };
+    let id: Uuid = diesel::insert_into(ill_requests::table)
+        .values((
+            ill_requests::role.eq(IllRole::Lending),
+            ill_requests::requesting_agency.eq(&requesting_agency),
+            ill_requests::supplying_agency.eq(agency_id().to_string()),
+            ill_requests::requesting_request_id.eq(&header.requesting_agency_request_id),
+            ill_requests::book_id.eq(book_id),
+            ill_requests::title.eq(title),
+            ill_requests::author.eq(&request.bibliographic_info.author),
+            ill_requests::isbn.eq(isbn),
+            ill_requests::status.eq(IllStatus::RequestReceived),
+            ill_requests::note.eq(request
+                .service_info
+                .as_ref()
+                .and_then(|info| info.note.clone())),
+        ))
+        .returning(ill_requests::ill_request_id)
+        .get_result(conn)?;
+    log_message(id, true, xml, conn)?;
+    Ok(Ok(()))
+}
+
+/// A partner we borrow from reporting on our request.
+fn receive_status(
+    update: &SupplyingAgencyMessage,
+    partner: &AgencyId,
+    xml: &str,
+    conn: &mut PgConnection,
+) -> Result<std::result::Result<(), Refusal>> {
+    let header = &update.header;
+    if &header.supplying_agency_id != partner {
+        return Ok(Err(not_partner(
+            "supplyingAgencyId",
+            &header.supplying_agency_id,
+        )));
+    }
+    let request: Option<IllRequest> = ill_requests::table
+        .filter(ill_requests::role.eq(IllRole::Borrowing))
+        .filter(ill_requests::requesting_agency.eq(header.requesting_agency_id.to_string()))
+        .filter(ill_requests::requesting_request_id.eq(&header.requesting_agency_request_id))
+        .filter(ill_requests::supplying_agency.eq(header.supplying_agency_id.to_string()))
+        .for_update()
+        .select(IllRequest::as_select())
+        .first(conn)
+        .optional()?;
+    let Some(request) = request else {
+        return Ok(Err((
+            ErrorType::UnrecognisedDataValue,
+            format!(
+                "requestingAgencyRequestId {}",
+                header.requesting_agency_request_id
+            ),
+        )));
+    };
+    if !SUPPLYING_REASONS.contains(&update.message_info.reason_for_message.trim()) {
+        return Ok(Err((
+            ErrorType::UnsupportedReasonForMessageType,
+            update.message_info.reason_for_message.clone(),
+        )));
+    }
+    let Some(status) = IllStatus::from_iso_name(&update.status_info.status) else {
+        return Ok(Err((
+            ErrorType::UnrecognisedDataValue,
+            format!("status {}", update.status_info.status),
+        )));
+    };
+    if !supplier_may_move(request.status, status) {
+        return Ok(Err((
+            ErrorType::UnrecognisedDataValue,
+            format!(
+                "status {} while the request is {:?}",
+                update.status_info.status, request.status
+            ),
+        )));
+    }
+    let due_date = update.status_info.due_date.as_deref().and_then(parse_date);
+    if status == IllStatus::Loaned && due_date.is_none() {
+        return Ok(Err((
+            ErrorType::UnrecognisedDataValue,
+            "Loaned without a dueDate".to_string(),
+        )));
+    }
+    if status == IllStatus::LoanCompleted {
+        if let Some(book_id) = request.book_id {
+            update_book_status(book_id, false, conn)?;
+        }
+    }
+    set_status(
+        request.ill_request_id,
+        status,
+        due_date,
+        update.message_info.note.as_deref(),
+        conn,
+    )?;
+    log_message(request.ill_request_id, true, xml, conn)?;
+    Ok(Ok(()))
+}
+
+/// A partner borrowing from us acting on their request: cancelling it, or
+/// telling us they received or sent back the book.
+fn receive_action(
+    action: &RequestingAgencyMessage,
+    partner: &AgencyId,
+    xml: &str,
+    conn: &mut PgConnection,
+) -> Result<std::result::Result<(), Refusal>> {
+    let header = &action.header;
+    if &header.requesting_agency_id != partner {
+        return Ok(Err(not_partner(
+            "requestingAgencyId",
+            &header.requesting_agency_id,
+        )));
+    }
+    let request: Option<IllRequest> = ill_requests::table
+        .filter(ill_requests::role.eq(IllRole::Lending))
+        .filter(ill_requests::requesting_agency.eq(header.requesting_agency_id.to_string()))
+        .filter(ill_requests::requesting_request_id.eq(&header.requesting_agency_request_id))
+        .for_update()
+        .select(IllRequest::as_select())
+        .first(conn)
+        .optional()?;
+    let Some(request) = request else {
+        return Ok(Err((
+            ErrorType::UnrecognisedDataValue,
+            format!(
+                "requestingAgencyRequestId {}",
+                header.requesting_agency_request_id
+            ),
+        )));
+    };
+    let name = action.action.trim();
+    if !action_allowed(name, request.status) {
+        return Ok(Err((
+            ErrorType::UnrecognisedDataValue,
+            format!("action {name} while the request is {:?}", request.status),
+        )));
+    }
+    match name {
+        "Cancel" => {
+            set_status(
+                request.ill_request_id,
+                IllStatus::Cancelled,
+                None,
+                action.note.as_deref(),
+                conn,
+            )?;
+        }
+        // staff see these in the message log and close the loan once the book is back
+        "Received" | "ShippedReturn" | "Notification" => {}
+        other => return Ok(Err((ErrorType::UnsupportedActionType, other.to_string()))),
+    }
+    log_message(request.ill_request_id, true, xml, conn)?;
+    Ok(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partners_answer_open_requests() {
+        assert!(supplier_may_move(
+            IllStatus::Requested,
+            IllStatus::WillSupply
+        ));
+        assert!(supplier_may_move(IllStatus::WillSupply, IllStatus::Loaned));
+        assert!(supplier_may_move(
+            IllStatus::RetryPossible,
+            IllStatus::Unfilled
+        ));
+        assert!(!supplier_may_move(IllStatus::Requested, IllStatus::Overdue));
+    }
+
+    #[test]
+    fn partners_update_loans_until_completed() {
+        assert!(supplier_may_move(IllStatus::Loaned, IllStatus::Loaned));
+        assert!(supplier_may_move(IllStatus::Loaned, IllStatus::Recalled));
+        assert!(supplier_may_move(
+            IllStatus::Overdue,
+            IllStatus::LoanCompleted
+        ));
+        assert!(!supplier_may_move(IllStatus::Loaned, IllStatus::Cancelled));
+    }
+
+    #[test]
+    fn finished_requests_stay_finished() {
+        for from in [
+            IllStatus::Submitted,
+            IllStatus::Unfilled,
+            IllStatus::LoanCompleted,
+            IllStatus::Cancelled,
+        ] {
+            assert!(!supplier_may_move(from, IllStatus::Loaned), "{from:?}");
+        }
+    }
+
+    #[test]
+    fn actions_need_a_matching_request() {
+        assert!(action_allowed("Cancel", IllStatus::RequestReceived));
+        assert!(!action_allowed("Cancel", IllStatus::Loaned));
+        assert!(!action_allowed("Cancel", IllStatus::Cancelled));
+        assert!(action_allowed("ShippedReturn", IllStatus::Overdue));
+        assert!(!action_allowed("Received", IllStatus::WillSupply));
+        assert!(action_allowed("Notification", IllStatus::LoanCompleted));
+    }
+}
@@ -1,2 +1,4 @@
 pub mod handlers;
 pub mod models;
+#[cfg(test)]
+mod tests;
@@ -1,24 +1,121 @@
 use crate::{
+    auth::{extractors::Principal, permissions::Permission},
     db::establish_connection,
-    loans::models::{create_loan, get_loan, return_book, LoanStatus, NewLoan},
+    errors::error_response,
+    loans::models::{
+        claim_returned, create_loan, create_loans, declare_lost, export_loans, get_loan,
+        get_loan_history, get_member_loans, recall_loan, renew_loan, return_book, search_loans,
+        BatchLoan, LoanSearch, LoanStatus, MemberLoansFilter, NewLoan, RecallRequest,
+    },
+    members::models::get_member,
+    notices::models::{send_recall_notice, NoticeConfig},
+    pagination::Pagination,
 };
-use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use actix_web::{delete, get, http::header, post, web, HttpResponse, Responder};
 
 #[post("/loans/new")]
-async fn new_loan(payload: web::Json<NewLoan>) -> impl Responder {
+async fn new_loan(principal: Principal, payload: web::Json<NewLoan>) -> impl Responder {
+    if let Err(denied) = principal.require_for(payload.member_id, Permission::CheckOut) {
+        return denied;
+    }
+    if payload.due_date.is_some() {
+        if let Err(denied) = principal.require(Permission::OverrideDueDate) {
+            return denied;
+        }
+    }
     let mut conn = establish_connection();
-    match create_loan(payload, &mut conn).await {
+    match create_loan(&payload, &mut conn).await {
         Ok(loan_id) => HttpResponse::Ok().json(loan_id),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Checks out a stack of books to one member, all of them or none.
+#[post("/loans/batch")]
+async fn new_loans(principal: Principal, payload: web::Json<BatchLoan>) -> impl Responder {
+    if let Err(denied) = principal.require_for(payload.member_id, Permission::CheckOut) {
+        return denied;
+    }
+    if payload.due_date.is_some() {
+        if let Err(denied) = principal.require(Permission::OverrideDueDate) {
+            return denied;
+        }
+    }
+    let mut conn = establish_connection();
+    match create_loans(&payload, &mut conn) {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Staff need [`Permission::ReadLoans`]; members may list their own loans by
+/// filtering on their member id.
+fn require_search(principal: &Principal, search: &LoanSearch) -> Result<(), HttpResponse> {
+    match search.member_id {
+        Some(member_id) => principal.require_for(member_id, Permission::ReadLoans),
+        None => principal.require(Permission::ReadLoans),
+    }
+}
+
+#[get("/loans")]
+async fn fetch_loans(
+    principal: Principal,
+    search: web::Query<LoanSearch>,
+    pagination: web::Query<Pagination>,
+) -> impl Responder {
+    if let Err(denied) = require_search(&principal, &search) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+
+    match search_loans(&search, *pagination, &mut conn).await {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Every loan matching the `GET /loans` filters as CSV.
+#[get("/loans.csv")]
+async fn export_loans_csv(principal: Principal, search: web::Query<LoanSearch>) -> impl Responder {
+    if let Err(denied) = require_search(&principal, &search) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+
+    let loans = match export_loans(&search, &mut conn).await {
+        Ok(loans) => loans,
+        Err(e) => return error_response(&e),
+    };
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for loan in &loans {
+        if let Err(e) = writer.serialize(loan) {
+            return HttpResponse::InternalServerError().json(format!("{e}"));
+        }
+    }
+    match writer.into_inner() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/csv")
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"loans.csv\"",
+            ))
+            .body(body),
         Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
     }
 }
 
 #[get("/loans/{loan_id}")]
-async fn fetch_loan(loan_id: web::Path<uuid::Uuid>) -> impl Responder {
+async fn fetch_loan(principal: Principal, loan_id: web::Path<uuid::Uuid>) -> impl Responder {
     let mut conn = establish_connection();
 
     match get_loan(*loan_id, &mut conn).await {
-        Ok(Some(loan)) => HttpResponse::Ok().json(loan),
+        Ok(Some(loan)) => match loan.member_id.map_or_else(
+            || principal.require(Permission::ReadLoans),
+            |borrower| principal.require_for(borrower, Permission::ReadLoans),
+        ) {
+            Ok(()) => HttpResponse::Ok().json(loan),
+            Err(denied) => denied,
+        },
         Ok(None) => HttpResponse::NotFound().json(format!("loan {loan_id} not found")),
         Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
     }
@@ -26,18 +123,160 @@ async fn fetch_loan(loan_id: web::Path<uuid::Uuid>) -> impl Responder {
 
 #[delete("/loans/{loan_id}")]
 async fn close_loan(
+    principal: Principal,
     loan_id: web::Path<uuid::Uuid>,
     status: web::Json<LoanStatus>,
 ) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::CheckIn) {
+        return denied;
+    }
     let mut conn = establish_connection();
     match return_book(*loan_id, *status, &mut conn).await {
-        Ok(updated) => {
-            if updated {
-                HttpResponse::Ok().finish()
-            } else {
-                HttpResponse::NotFound().finish()
-            }
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(e) => error_response(&e),
+    }
+}
+
+#[post("/loans/{loan_id}/renew")]
+async fn renew(principal: Principal, loan_id: web::Path<uuid::Uuid>) -> impl Responder {
+    let mut conn = establish_connection();
+
+    let loan = match get_loan(*loan_id, &mut conn).await {
+        Ok(Some(loan)) => loan,
+        Ok(None) => return HttpResponse::NotFound().json(format!("loan {loan_id} not found")),
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    };
+    let allowed = match loan.member_id {
+        Some(borrower) => principal.require_for(borrower, Permission::RenewLoans),
+        None => principal.require(Permission::RenewLoans),
+    };
+    if let Err(denied) = allowed {
+        return denied;
+    }
+
+    match renew_loan(*loan_id, &mut conn).await {
+        Ok(due_date) => HttpResponse::Ok().json(due_date),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Declares the book of a loan lost and bills its replacement cost. Checking
+/// the book in later refunds the charge.
+#[post("/loans/{loan_id}/lost")]
+async fn lost(principal: Principal, loan_id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::CheckIn) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+
+    match declare_lost(*loan_id, &mut conn).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Records that the member says the book came back although it was never
+/// checked in.
+#[post("/loans/{loan_id}/claims-returned")]
+async fn claims_returned(principal: Principal, loan_id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::CheckIn) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+
+    match claim_returned(*loan_id, &mut conn).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => error_response(&e),
+    }
+}
+
+/// Recalls a book for a member who needs it: the borrower gets a shorter due
+/// date and a notice, and the requester a hold on the book.
+#[post("/loans/{loan_id}/recall")]
+async fn recall(
+    principal: Principal,
+    loan_id: web::Path<uuid::Uuid>,
+    payload: web::Json<RecallRequest>,
+    config: web::Data<NoticeConfig>,
+) -> impl Responder {
+    if let Err(denied) = principal.require_for(payload.member_id, Permission::PlaceHolds) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    let mut report = match recall_loan(*loan_id, payload.member_id, &mut conn) {
+        Ok(report) => report,
+        Err(e) => return error_response(&e),
+    };
+    if let Some(transport) = &config.transport {
+        let today = chrono::Utc::now().date_naive();
+        match send_recall_notice(report.loan_id, today, transport.as_ref(), &mut conn) {
+            Ok(notified) => report.notified = notified,
+            Err(e) => log::error!("recall notice for loan {} failed: {e}", report.loan_id),
+        }
+    }
+    HttpResponse::Ok().json(report)
+}
+
+#[get("/members/{member_id}/loans")]
+async fn fetch_member_loans(
+    principal: Principal,
+    member_id: web::Path<uuid::Uuid>,
+    filter: web::Query<MemberLoansFilter>,
+    pagination: web::Query<Pagination>,
+) -> impl Responder {
+    if let Err(denied) = principal.require_for(*member_id, Permission::ReadLoans) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+
+    match get_member(*member_id, &mut conn) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(format!("member {member_id} not found")),
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+
+    match get_member_loans(*member_id, filter.status, *pagination, &mut conn).await {
+        Ok(loans) => HttpResponse::Ok().json(loans),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+/// The full reading history of a member as CSV. Returned loans only show up
+/// here for members who keep their history.
+#[get("/members/{member_id}/history.csv")]
+async fn export_loan_history(
+    principal: Principal,
+    member_id: web::Path<uuid::Uuid>,
+) -> impl Responder {
+    if let Err(denied) = principal.require_for(*member_id, Permission::ReadLoans) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+
+    match get_member(*member_id, &mut conn) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(format!("member {member_id} not found")),
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+
+    let history = match get_loan_history(*member_id, &mut conn).await {
+        Ok(history) => history,
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    };
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for loan in &history {
+        if let Err(e) = writer.serialize(loan) {
+            return HttpResponse::InternalServerError().json(format!("{e}"));
         }
+    }
+    match writer.into_inner() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type("text/csv")
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"history-{member_id}.csv\""),
+            ))
+            .body(body),
         Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
     }
 }
@@ -1,36 +1,44 @@
 use std::io::Write;
 
-use actix_web::{
-    error::{ErrorBadRequest, ErrorInternalServerError},
-    web,
-};
+use actix_web::error::ErrorBadRequest;
 use anyhow::Context;
 use anyhow::Result;
 
-use chrono::{DateTime, NaiveDate};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
 use diesel::{
     deserialize::FromSql,
+    helper_types::{InnerJoin, IntoBoxed, LeftJoin},
     pg::{Pg, PgValue},
     prelude::{Insertable, Queryable},
     result::Error::NotFound,
     serialize::{IsNull, ToSql},
-    AsChangeset, AsExpression, ExpressionMethods, FromSqlRow, OptionalExtension, PgConnection,
-    QueryDsl, RunQueryDsl, Selectable,
+    AsChangeset, AsExpression, BoolExpressionMethods, Connection, ExpressionMethods, FromSqlRow,
+    NullableExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl, Selectable,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    books::models::{get_book, update_book_status},
+    blocks::models::check_member_blocks,
+    books::models::{get_book, update_book_status, Book},
+    db::{is_serialization_failure, with_retry},
     errors::LibError,
-    members::models::{get_member, update_member, NewMember},
-    schema::loans,
+    fines::models::{assess_fine, bill_replacement, check_balance, refund_replacement},
+    holds::models::{advance_queue, count_waiting, fulfil_hold, get_active_hold, place_hold, Hold},
+    households::models::check_household_limit,
+    ill::models::get_borrowed_copy,
+    members::models::{get_member, Member},
+    pagination::Pagination,
+    policy::models::{due_date, next_open_day, require_rule, LoanContext},
+    schema::{books, loan_statistics, loans, members},
 };
 
 #[derive(Debug, Deserialize)]
 pub struct NewLoan {
     pub member_id: uuid::Uuid,
     pub book_id: uuid::Uuid,
-    pub due_date: i32,
+    /// Overrides the due date from the circulation rules.
+    #[serde(default)]
+    pub due_date: Option<NaiveDate>,
 }
 
 #[derive(Debug, Insertable)]
@@ -46,9 +54,16 @@ pub struct LoanRequest {
 #[derive(Debug, PartialEq, FromSqlRow, AsExpression, Eq, Serialize, Deserialize, Clone, Copy)]
 #[diesel(sql_type = crate::schema::sql_types::LoanStatus)]
 pub enum LoanStatus {
+    #[serde(alias = "open")]
     Open,
+    #[serde(alias = "returned")]
     Returned,
+    #[serde(alias = "overdue")]
     Overdue,
+    #[serde(alias = "lost")]
+    Lost,
+    #[serde(alias = "claims_returned")]
+    ClaimsReturned,
 }
 
 impl ToSql<crate::schema::sql_types::LoanStatus, Pg> for LoanStatus {
@@ -60,6 +75,8 @@ impl ToSql<crate::schema::sql_types::LoanStatus, Pg> for LoanStatus {
             LoanStatus::Open => out.write_all(b"open")?,
             LoanStatus::Returned => out.write_all(b"returned")?,
             LoanStatus::Overdue => out.write_all(b"overdue")?,
+            LoanStatus::Lost => out.write_all(b"lost")?,
+            LoanStatus::ClaimsReturned => out.write_all(b"claims_returned")?,
         }
         Ok(IsNull::No)
     }
@@ -71,6 +88,8 @@ impl FromSql<crate::schema::sql_types::LoanStatus, Pg> for LoanStatus {
             b"open" => Ok(LoanStatus::Open),
             b"returned" => Ok(LoanStatus::Returned),
             b"overdue" => Ok(LoanStatus::Overdue),
+            b"lost" => Ok(LoanStatus::Lost),
+            b"claims_returned" => Ok(LoanStatus::ClaimsReturned),
             _ => Err("Unrecognized enum variant".into()),
         }
     }
@@ -80,27 +99,292 @@ impl FromSql<crate::schema::sql_types::LoanStatus, Pg> for LoanStatus {
 #[diesel(table_name = loans)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Loan {
-    loan_id: uuid::Uuid,
-    member_id: uuid::Uuid,
-    book_id: uuid::Uuid,
-    loan_date: chrono::NaiveDate,
-    due_date: chrono::NaiveDate,
-    return_date: Option<chrono::NaiveDate>,
-    status: LoanStatus,
+    pub loan_id: uuid::Uuid,
+    /// Cleared once the loan is returned, unless the borrower keeps their
+    /// reading history.
+    pub member_id: Option<uuid::Uuid>,
+    pub book_id: uuid::Uuid,
+    pub loan_date: chrono::NaiveDate,
+    pub due_date: chrono::NaiveDate,
+    pub return_date: Option<chrono::NaiveDate>,
+    pub status: LoanStatus,
+    pub renewal_count: i32,
+    pub recalled_at: Option<NaiveDateTime>,
+    /// The hold of the member who recalled the book.
+    pub recall_hold_id: Option<uuid::Uuid>,
 }
 
-pub async fn create_loan(
-    payload: web::Json<NewLoan>,
-    conn: &mut PgConnection,
-) -> Result<uuid::Uuid> {
-    let book = get_book(payload.book_id, conn)?.ok_or(NotFound)?;
-    if !book.availability_status {
-        return Err(LibError::ActixError(
-            ErrorBadRequest("Book is not available to loan").to_string(),
-        )
-        .into());
+impl Loan {
+    /// Whether the book is still with the member.
+    pub fn is_out(&self) -> bool {
+        matches!(self.status, LoanStatus::Open | LoanStatus::Overdue)
     }
-    let member = match get_member(payload.member_id, conn) {
+}
+
+/// Why a loan could not be renewed.
+#[derive(Debug, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum RenewalRefusal {
+    NotOpen,
+    MaxRenewalsReached {
+        max_renewals: i32,
+    },
+    TooOverdue {
+        days_overdue: i64,
+        limit: i32,
+    },
+    /// Other members are waiting for the book.
+    OnHold {
+        waiting: i64,
+    },
+    /// Another member recalled the book.
+    Recalled,
+    /// The copy was borrowed from a partner library that wants it back by
+    /// then.
+    PartnerDueDate {
+        partner_due_date: NaiveDate,
+    },
+}
+
+/// A loan as shown in a member's loan history, with the title of the borrowed book.
+#[derive(Debug, Queryable, Serialize)]
+pub struct MemberLoan {
+    pub loan_id: uuid::Uuid,
+    pub book_id: uuid::Uuid,
+    pub title: String,
+    pub loan_date: chrono::NaiveDate,
+    pub due_date: chrono::NaiveDate,
+    pub return_date: Option<chrono::NaiveDate>,
+    pub status: LoanStatus,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LoanSummary {
+    pub total: i64,
+    pub open: i64,
+    pub returned: i64,
+    pub overdue: i64,
+    pub lost: i64,
+    pub claims_returned: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemberLoans {
+    pub member_id: uuid::Uuid,
+    pub page: i64,
+    pub per_page: i64,
+    pub summary: LoanSummary,
+    pub loans: Vec<MemberLoan>,
+}
+
+/// Filters of `GET /loans`. All of them are optional and combine with AND;
+/// date ranges include both ends.
+#[derive(Debug, Default, Deserialize)]
+pub struct LoanSearch {
+    pub status: Option<LoanStatus>,
+    pub member_id: Option<uuid::Uuid>,
+    pub book_id: Option<uuid::Uuid>,
+    pub loan_from: Option<NaiveDate>,
+    pub loan_to: Option<NaiveDate>,
+    pub due_from: Option<NaiveDate>,
+    pub due_to: Option<NaiveDate>,
+    /// Open loans due between today and this many days from now.
+    pub due_within_days: Option<i64>,
+}
+
+/// The furthest ahead `due_within_days` looks, well past any due date a loan
+/// can get.
+const MAX_DUE_WITHIN_DAYS: i64 = 3650;
+
+impl LoanSearch {
+    fn validate(&self) -> Result<()> {
+        let bad_request = |message: String| -> Result<()> {
+            Err(LibError::ActixError(ErrorBadRequest(message).to_string()).into())
+        };
+        if self
+            .due_within_days
+            .is_some_and(|days| !(0..=MAX_DUE_WITHIN_DAYS).contains(&days))
+        {
+            return bad_request(format!(
+                "due_within_days must be between 0 and {MAX_DUE_WITHIN_DAYS}"
+            ));
+        }
+        if let (Some(from), Some(to)) = (self.loan_from, self.loan_to) {
+            if from > to {
+                return bad_request("loan_from is after loan_to".to_string());
+            }
+        }
+        if let (Some(from), Some(to)) = (self.due_from, self.due_to) {
+            if from > to {
+                return bad_request("due_from is after due_to".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A loan as listed by `GET /loans`, with the book title and borrower name.
+#[derive(Debug, Queryable, Serialize)]
+pub struct LoanListing {
+    pub loan_id: uuid::Uuid,
+    pub book_id: uuid::Uuid,
+    pub title: String,
+    pub member_id: Option<uuid::Uuid>,
+    pub member_name: Option<String>,
+    pub loan_date: chrono::NaiveDate,
+    pub due_date: chrono::NaiveDate,
+    pub return_date: Option<chrono::NaiveDate>,
+    pub status: LoanStatus,
+    pub renewal_count: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoanPage {
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+    pub loans: Vec<LoanListing>,
+}
+
+/// What a check-in did besides closing the loan.
+#[derive(Debug, Serialize)]
+pub struct CheckinReport {
+    pub loan_id: uuid::Uuid,
+    pub book_id: uuid::Uuid,
+    pub member_id: Option<uuid::Uuid>,
+    pub status: LoanStatus,
+    /// How much the late fee of the loan went up.
+    pub fine_cents: i32,
+    /// The replacement charge given back for a lost book that turned up.
+    pub refund_cents: i32,
+    /// The hold the book went on the hold shelf for.
+    pub hold: Option<Hold>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LostReport {
+    pub loan_id: uuid::Uuid,
+    pub member_id: uuid::Uuid,
+    /// How much the late fee went up before it stopped accruing.
+    pub fine_cents: i32,
+    pub replacement_cents: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClaimReport {
+    pub loan_id: uuid::Uuid,
+    pub member_id: uuid::Uuid,
+    /// How much the late fee went up before it stopped accruing.
+    pub fine_cents: i32,
+    /// Claims the member has made so far, this one included.
+    pub claims_returned_count: i32,
+}
+
+/// Several books checked out to one member at once.
+#[derive(Debug, Deserialize)]
+pub struct BatchLoan {
+    pub member_id: uuid::Uuid,
+    pub book_ids: Vec<uuid::Uuid>,
+    /// Overrides the due date from the circulation rules for every book.
+    #[serde(default)]
+    pub due_date: Option<NaiveDate>,
+}
+
+/// What a batch checkout did with one of its books.
+#[derive(Debug, Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum BatchOutcome {
+    CheckedOut {
+        loan_id: uuid::Uuid,
+    },
+    Refused {
+        error: String,
+    },
+    /// The book could be checked out, but another book of the batch was refused.
+    RolledBack,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchItem {
+    pub book_id: uuid::Uuid,
+    #[serde(flatten)]
+    pub outcome: BatchOutcome,
+}
+
+/// One line of a [`LoanReceipt`].
+#[derive(Debug, Queryable, Serialize)]
+pub struct ReceiptLine {
+    pub loan_id: uuid::Uuid,
+    pub book_id: uuid::Uuid,
+    pub title: String,
+    pub due_date: NaiveDate,
+}
+
+/// Every loan of a batch checkout on one slip, in the order the books were
+/// listed.
+#[derive(Debug, Serialize)]
+pub struct LoanReceipt {
+    pub member_id: uuid::Uuid,
+    pub member_name: String,
+    pub loan_date: NaiveDate,
+    pub loans: Vec<ReceiptLine>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchReport {
+    pub items: Vec<BatchItem>,
+    pub receipt: LoanReceipt,
+}
+
+/// The member who wants a book back early.
+#[derive(Debug, Deserialize)]
+pub struct RecallRequest {
+    pub member_id: uuid::Uuid,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecallReport {
+    pub loan_id: uuid::Uuid,
+    /// The borrower, who is owed a recall notice.
+    pub member_id: uuid::Uuid,
+    pub hold_id: uuid::Uuid,
+    pub due_date: NaiveDate,
+    /// Whether the recall notice went out right away; otherwise the next
+    /// notice run sends it.
+    pub notified: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MemberLoansFilter {
+    pub status: Option<LoanStatus>,
+}
+
+/// Checks a book out to a member.
+///
+/// Everything happens in one transaction that locks the book and then the
+/// member row, so two checkouts of the same book cannot both succeed and a
+/// failure leaves nothing half done.
+pub async fn create_loan(payload: &NewLoan, conn: &mut PgConnection) -> Result<uuid::Uuid> {
+    with_retry(conn, |conn| {
+        conn.transaction(|conn| checkout(payload, conn))
+    })
+}
+
+/// The body of [`create_loan`], for callers that already run a transaction.
+pub fn checkout(payload: &NewLoan, conn: &mut PgConnection) -> Result<uuid::Uuid> {
+    // book before member, the same order check-in takes its locks in
+    let book: Book = books::table
+        .find(payload.book_id)
+        .for_update()
+        .first(conn)
+        .optional()?
+        .ok_or(NotFound)?;
+    let member: Member = match members::table
+        .find(payload.member_id)
+        .for_update()
+        .first(conn)
+        .optional()
+    {
         Ok(Some(member)) => member,
         _ => {
             return Err(LibError::ActixError(
@@ -109,34 +393,68 @@ pub async fn create_loan(
             .into());
         }
     };
+    if member.anonymized_at.is_some() {
+        return Err(LibError::ActixError(
+            ErrorBadRequest("Member account has been anonymized").to_string(),
+        )
+        .into());
+    }
+    if member
+        .expires_on
+        .is_some_and(|expires_on| expires_on < chrono::Utc::now().date_naive())
+    {
+        return Err(LibError::ActixError(
+            ErrorBadRequest("Member account has expired").to_string(),
+        )
+        .into());
+    }
+
+    check_member_blocks(member.member_id, conn)?;
+    check_balance(member.member_id, conn)?;
+    check_household_limit(member.member_id, conn)?;
+
+    let today = chrono::Utc::now().date_naive();
+    let context = LoanContext {
+        tier: member.tier,
+        item_type: book.item_type,
+        branch: book.branch,
+    };
+    let mut due_date = due_date(&context, payload.due_date, today, conn)?;
 
-    let due_date =
-        match chrono::naive::NaiveDateTime::from_timestamp_opt(payload.due_date as i64, 0) {
-            Some(t) => t.date(),
-            _ => {
-                return Err(
-                    LibError::Chrono(String::from("cannot convert timestamp to date")).into(),
-                )
-            }
-        };
+    // a copy borrowed from a partner library goes to the member who asked for
+    // it, and has to be back before the partner wants it
+    if let Some(request) = get_borrowed_copy(payload.book_id, conn)? {
+        if request.member_id != Some(payload.member_id) {
+            return Err(LibError::ActixError(
+                ErrorBadRequest("Book was borrowed from a partner for another member").to_string(),
+            )
+            .into());
+        }
+        if let Some(partner_due_date) = request.due_date {
+            due_date = due_date.min(partner_due_date);
+        }
+    }
+
+    // a book on the hold shelf can only go to the member it is held for
+    if !book.availability_status && !fulfil_hold(payload.book_id, payload.member_id, conn)? {
+        return Err(LibError::ActixError(
+            ErrorBadRequest("Book is not available to loan, place a hold instead").to_string(),
+        )
+        .into());
+    }
 
     let new_loan = LoanRequest {
         member_id: payload.member_id,
         book_id: payload.book_id,
-        loan_date: chrono::Utc::now().date_naive(),
+        loan_date: today,
         due_date,
         return_date: None,
         status: LoanStatus::Open,
     };
 
-    let update = NewMember {
-        name: member.name,
-        email: member.email,
-        privilege: member.privilege,
-        borrowed: member.borrowed + 1,
-    };
-
-    update_member(member.member_id, update, conn)?;
+    diesel::update(members::table.find(member.member_id))
+        .set(members::borrowed.eq(members::borrowed + 1))
+        .execute(conn)?;
 
     update_book_status(payload.book_id, false, conn)?;
 
@@ -148,6 +466,109 @@ pub async fn create_loan(
     Ok(id)
 }
 
+/// Checks out every book of `payload` or none of them.
+///
+/// Each book gets its own savepoint, so a refused book does not stop the
+/// others from being tried and every book gets a result. When any was
+/// refused, the whole batch is rolled back with [`LibError::BatchRefused`].
+pub fn create_loans(payload: &BatchLoan, conn: &mut PgConnection) -> Result<BatchReport> {
+    if payload.book_ids.is_empty() {
+        return Err(
+            LibError::ActixError(ErrorBadRequest("No books to check out").to_string()).into(),
+        );
+    }
+    with_retry(conn, |conn| {
+        conn.transaction(|conn| checkout_batch(payload, conn))
+    })
+}
+
+fn checkout_batch(payload: &BatchLoan, conn: &mut PgConnection) -> Result<BatchReport> {
+    // take every lock up front in the order a single checkout does, books (by
+    // id, as another batch would) before the member, so neither a batch nor a
+    // check-in can end up waiting on the other
+    let mut book_ids = payload.book_ids.clone();
+    book_ids.sort();
+    book_ids.dedup();
+    books::table
+        .filter(books::book_id.eq_any(&book_ids))
+        .order(books::book_id)
+        .select(books::book_id)
+        .for_update()
+        .load::<uuid::Uuid>(conn)?;
+    members::table
+        .find(payload.member_id)
+        .select(members::member_id)
+        .for_update()
+        .first::<uuid::Uuid>(conn)
+        .optional()?;
+
+    let mut items = Vec::with_capacity(payload.book_ids.len());
+    for (position, &book_id) in payload.book_ids.iter().enumerate() {
+        let outcome = if payload.book_ids[..position].contains(&book_id) {
+            BatchOutcome::Refused {
+                error: "Book is listed more than once".to_string(),
+            }
+        } else {
+            let loan = NewLoan {
+                member_id: payload.member_id,
+                book_id,
+                due_date: payload.due_date,
+            };
+            match conn.transaction(|conn| checkout(&loan, conn)) {
+                Ok(loan_id) => BatchOutcome::CheckedOut { loan_id },
+                // the transaction is lost, let with_retry start over
+                Err(e) if is_serialization_failure(&e) => return Err(e),
+                Err(e) => BatchOutcome::Refused {
+                    error: e.to_string(),
+                },
+            }
+        };
+        items.push(BatchItem { book_id, outcome });
+    }
+
+    if items
+        .iter()
+        .any(|item| matches!(item.outcome, BatchOutcome::Refused { .. }))
+    {
+        for item in &mut items {
+            if matches!(item.outcome, BatchOutcome::CheckedOut { .. }) {
+                item.outcome = BatchOutcome::RolledBack;
+            }
+        }
+        return Err(LibError::BatchRefused(items).into());
+    }
+
+    let member = get_member(payload.member_id, conn)?.ok_or(NotFound)?;
+    let mut lines: Vec<ReceiptLine> = loans::table
+        .inner_join(books::table)
+        .filter(loans::member_id.eq(payload.member_id))
+        .filter(loans::book_id.eq_any(&payload.book_ids))
+        .filter(loans::status.eq(LoanStatus::Open))
+        .select((
+            loans::loan_id,
+            loans::book_id,
+            books::title,
+            loans::due_date,
+        ))
+        .load(conn)?;
+    lines.sort_by_key(|line| {
+        payload
+            .book_ids
+            .iter()
+            .position(|&book_id| book_id == line.book_id)
+    });
+
+    Ok(BatchReport {
+        items,
+        receipt: LoanReceipt {
+            member_id: member.member_id,
+            member_name: member.name,
+            loan_date: chrono::Utc::now().date_naive(),
+            loans: lines,
+        },
+    })
+}
+
 fn update_loan_status(
     loan_id: uuid::Uuid,
     status: LoanStatus,
@@ -156,48 +577,404 @@ fn update_loan_status(
     diesel::update(loans::table.filter(loans::loan_id.eq(loan_id)))
         .set(loans::status.eq(status))
         .execute(conn)
-        .with_context(|| {
-            LibError::ActixError(
-                ErrorInternalServerError("failed to update loan status").to_string(),
-            )
-        })
+        .with_context(|| LibError::DbError("failed to update loan status".to_string()))
 }
 
-// close function
+/// Sets the status of an open loan. [`LoanStatus::Returned`] checks the book
+/// in: it goes to the next hold or back on the shelf, late fees are charged
+/// and the member borrows one book less, all in one transaction that locks
+/// the loan first. Closed loans are refused, so a book cannot be checked in
+/// twice, except that a lost or claims-returned book that turns up can be
+/// returned, refunding its replacement charge.
 pub async fn return_book(
     payload: uuid::Uuid,
     status: LoanStatus,
     conn: &mut PgConnection,
-) -> Result<bool> {
-    match get_loan(payload, conn).await {
-        Ok(Some(db_loan)) => {
-            update_loan_status(db_loan.loan_id, status, conn)?;
-            update_book_status(db_loan.loan_id, true, conn)?;
-            if status == LoanStatus::Returned {
-                update_loan_return_date(payload, DateTime::date_naive(&chrono::Utc::now()), conn)?;
-            }
-            let member = get_member(db_loan.member_id, conn)?
-                .expect("invalid member credentials submitted to loan. Please verify member and loan databases");
-
-            let borrowed = if member.borrowed <= 0 {
-                member.borrowed
+) -> Result<CheckinReport> {
+    with_retry(conn, |conn| {
+        conn.transaction(|conn| checkin(payload, status, conn))
+    })
+}
+
+/// The body of [`return_book`], for callers that already run a transaction.
+pub fn checkin(
+    loan_id: uuid::Uuid,
+    status: LoanStatus,
+    conn: &mut PgConnection,
+) -> Result<CheckinReport> {
+    if matches!(status, LoanStatus::Lost | LoanStatus::ClaimsReturned) {
+        return Err(LibError::ActixError(
+            ErrorBadRequest(format!(
+                "declare loan {loan_id} lost or claims returned through its own endpoint"
+            ))
+            .to_string(),
+        )
+        .into());
+    }
+    let db_loan = lock_loan(loan_id, conn)?;
+    let found = matches!(
+        db_loan.status,
+        LoanStatus::Lost | LoanStatus::ClaimsReturned
+    );
+    if !(db_loan.is_out() || found && status == LoanStatus::Returned) {
+        return Err(LibError::ActixError(
+            ErrorBadRequest(format!("loan {loan_id} is already closed")).to_string(),
+        )
+        .into());
+    }
+
+    update_loan_status(db_loan.loan_id, status, conn)?;
+    let mut report = CheckinReport {
+        loan_id,
+        book_id: db_loan.book_id,
+        member_id: db_loan.member_id,
+        status,
+        fine_cents: 0,
+        refund_cents: 0,
+        hold: None,
+    };
+    if status != LoanStatus::Returned {
+        return Ok(report);
+    }
+
+    let today = DateTime::date_naive(&chrono::Utc::now());
+    books::table
+        .find(db_loan.book_id)
+        .select(books::book_id)
+        .for_update()
+        .first::<uuid::Uuid>(conn)?;
+    update_loan_return_date(loan_id, today, conn)?;
+    report.hold = advance_queue(db_loan.book_id, today, conn)?;
+
+    let member = loan_member(&db_loan, conn)?;
+    if db_loan.status == LoanStatus::Lost {
+        report.refund_cents = refund_replacement(&db_loan, member.member_id, conn)?;
+    } else if !found {
+        // a found book was released from the member when it went missing
+        report.fine_cents = release_loan(&db_loan, &member, today, conn)?;
+    }
+    if !member.keep_history {
+        detach_returned_loans(member.member_id, conn)?;
+    }
+    Ok(report)
+}
+
+/// Closes a loan whose book the member lost. Its late fee stops growing and
+/// the member is billed the replacement cost of the book. A claims-returned
+/// loan can still be declared lost when the book does not turn up.
+pub async fn declare_lost(loan_id: uuid::Uuid, conn: &mut PgConnection) -> Result<LostReport> {
+    with_retry(conn, |conn| {
+        conn.transaction(|conn| {
+            let loan = lock_loan(loan_id, conn)?;
+            if !(loan.is_out() || loan.status == LoanStatus::ClaimsReturned) {
+                return Err(LibError::ActixError(
+                    ErrorBadRequest(format!("loan {loan_id} is not out")).to_string(),
+                )
+                .into());
+            }
+            let member = loan_member(&loan, conn)?;
+            let fine_cents = if loan.is_out() {
+                release_loan(&loan, &member, chrono::Utc::now().date_naive(), conn)?
             } else {
-                member.borrowed - 1
+                0
             };
+            update_loan_status(loan_id, LoanStatus::Lost, conn)?;
+            Ok(LostReport {
+                loan_id,
+                member_id: member.member_id,
+                fine_cents,
+                replacement_cents: bill_replacement(&loan, member.member_id, conn)?,
+            })
+        })
+    })
+}
 
-            let update = NewMember {
-                name: member.name,
-                email: member.email,
-                privilege: member.privilege,
-                borrowed,
+/// Closes a loan the member says they returned although it was never checked
+/// in. Its late fee stops growing and the claim is counted against the
+/// member for staff to review; nothing is billed until the loan is declared
+/// lost.
+pub async fn claim_returned(loan_id: uuid::Uuid, conn: &mut PgConnection) -> Result<ClaimReport> {
+    with_retry(conn, |conn| {
+        conn.transaction(|conn| {
+            let loan = lock_loan(loan_id, conn)?;
+            if !loan.is_out() {
+                return Err(LibError::ActixError(
+                    ErrorBadRequest(format!("loan {loan_id} is not out")).to_string(),
+                )
+                .into());
+            }
+            let member = loan_member(&loan, conn)?;
+            let fine_cents = release_loan(&loan, &member, chrono::Utc::now().date_naive(), conn)?;
+            update_loan_status(loan_id, LoanStatus::ClaimsReturned, conn)?;
+            let claims_returned_count = diesel::update(members::table.find(member.member_id))
+                .set(members::claims_returned_count.eq(members::claims_returned_count + 1))
+                .returning(members::claims_returned_count)
+                .get_result(conn)?;
+            Ok(ClaimReport {
+                loan_id,
+                member_id: member.member_id,
+                fine_cents,
+                claims_returned_count,
+            })
+        })
+    })
+}
+
+fn lock_loan(loan_id: uuid::Uuid, conn: &mut PgConnection) -> Result<Loan> {
+    loans::table
+        .find(loan_id)
+        .for_update()
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| LibError::DbError(format!("loan {loan_id} not found")).into())
+}
+
+fn loan_member(loan: &Loan, conn: &mut PgConnection) -> Result<Member> {
+    loan.member_id
+        .and_then(|member_id| get_member(member_id, conn).transpose())
+        .transpose()?
+        .ok_or_else(|| LibError::DbError(format!("loan {} has no member", loan.loan_id)).into())
+}
+
+/// Settles the late fee of a loan that stops being out as of `today` and
+/// takes it off the books the member borrows. Returns how much the fee went up.
+fn release_loan(
+    loan: &Loan,
+    member: &Member,
+    today: NaiveDate,
+    conn: &mut PgConnection,
+) -> Result<i32> {
+    let fine_cents = assess_fine(loan, member, today, conn)?;
+    diesel::update(
+        members::table
+            .find(member.member_id)
+            .filter(members::borrowed.gt(0)),
+    )
+    .set(members::borrowed.eq(members::borrowed - 1))
+    .execute(conn)?;
+    Ok(fine_cents)
+}
+
+/// Members of these tiers may recall books, besides privileged members, unless
+/// `RECALL_TIERS` lists others.
+pub const DEFAULT_RECALL_TIERS: &str = "faculty";
+
+/// How long a loan lasts at least once it was recalled, unless
+/// `RECALL_MIN_LOAN_DAYS` says otherwise.
+pub const DEFAULT_RECALL_MIN_LOAN_DAYS: i64 = 7;
+
+pub fn recall_min_loan_days() -> i64 {
+    std::env::var("RECALL_MIN_LOAN_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(DEFAULT_RECALL_MIN_LOAN_DAYS)
+}
+
+pub fn may_recall(member: &Member) -> bool {
+    let tiers = std::env::var("RECALL_TIERS").unwrap_or_else(|_| DEFAULT_RECALL_TIERS.to_string());
+    member.privilege || tiers.split(',').any(|tier| tier.trim() == member.tier)
+}
+
+/// Asks for a book back early on behalf of `requester_id`.
+///
+/// The loan becomes due [`recall_min_loan_days`] after it started, or today if
+/// that has passed, moved to a day the branch is open; a loan already due
+/// sooner keeps its due date. The requester is queued for the book, or keeps
+/// the hold they have, and that hold is linked to the loan. From now on the
+/// loan cannot be renewed and runs up late fees at the recall rate.
+pub fn recall_loan(
+    loan_id: uuid::Uuid,
+    requester_id: uuid::Uuid,
+    conn: &mut PgConnection,
+) -> Result<RecallReport> {
+    with_retry(conn, |conn| {
+        conn.transaction(|conn| {
+            let loan = lock_loan(loan_id, conn)?;
+            if !loan.is_out() {
+                return Err(LibError::ActixError(
+                    ErrorBadRequest("Only books that are out can be recalled").to_string(),
+                )
+                .into());
+            }
+            if loan.recalled_at.is_some() {
+                return Err(LibError::ActixError(
+                    ErrorBadRequest("Loan was already recalled").to_string(),
+                )
+                .into());
+            }
+            let borrower = loan_member(&loan, conn)?;
+            if borrower.member_id == requester_id {
+                return Err(LibError::ActixError(
+                    ErrorBadRequest("Members cannot recall their own loans").to_string(),
+                )
+                .into());
+            }
+            let requester = get_member(requester_id, conn)?.ok_or_else(|| {
+                LibError::ActixError(ErrorBadRequest("Invalid member credentials").to_string())
+            })?;
+            if !may_recall(&requester) {
+                return Err(LibError::ActixError(
+                    ErrorBadRequest("Member may not recall books").to_string(),
+                )
+                .into());
+            }
+
+            let hold_id = match get_active_hold(loan.book_id, requester_id, conn)? {
+                Some(hold_id) => hold_id,
+                None => place_hold(loan.book_id, requester_id, conn)?,
             };
-            update_member(member.member_id, update, conn)?;
-            Ok(true)
-        }
-        Ok(None) => Err(LibError::DbError(format!("loan {payload} not found")).into()),
-        Err(e) => Err(LibError::DbError(e.to_string()).into()),
-    }
-    // TODO: Handle late fee calculations here.
+
+            let book = get_book(loan.book_id, conn)?.ok_or(NotFound)?;
+            let today = chrono::Utc::now().date_naive();
+            let shortened = next_open_day(
+                &book.branch,
+                (loan.loan_date + chrono::Duration::days(recall_min_loan_days())).max(today),
+                conn,
+            )?;
+            let due_date = loan.due_date.min(shortened);
+
+            diesel::update(loans::table.find(loan_id))
+                .set((
+                    loans::due_date.eq(due_date),
+                    loans::recalled_at.eq(diesel::dsl::now),
+                    loans::recall_hold_id.eq(hold_id),
+                ))
+                .execute(conn)?;
+
+            Ok(RecallReport {
+                loan_id,
+                member_id: borrower.member_id,
+                hold_id,
+                due_date,
+                notified: false,
+            })
+        })
+    })
+}
+
+/// Moves the returned loans of a member into `loan_statistics`, keeping only
+/// the book, dates and membership tier, and clears the member from the loans.
+pub fn detach_returned_loans(member_id: uuid::Uuid, conn: &mut PgConnection) -> Result<usize> {
+    conn.transaction(|conn| {
+        let returned: Vec<(uuid::Uuid, NaiveDate, Option<NaiveDate>, String)> = loans::table
+            .inner_join(members::table)
+            .filter(loans::member_id.eq(member_id))
+            .filter(loans::status.eq(LoanStatus::Returned))
+            .select((
+                loans::book_id,
+                loans::loan_date,
+                loans::return_date,
+                members::tier,
+            ))
+            .load(conn)?;
+        let today = chrono::Utc::now().date_naive();
+        let stats: Vec<_> = returned
+            .into_iter()
+            .map(|(book_id, loan_date, return_date, tier)| {
+                (
+                    loan_statistics::book_id.eq(book_id),
+                    loan_statistics::tier.eq(tier),
+                    loan_statistics::loan_date.eq(loan_date),
+                    loan_statistics::return_date.eq(return_date.unwrap_or(today)),
+                    loan_statistics::status.eq(LoanStatus::Returned),
+                )
+            })
+            .collect();
+        diesel::insert_into(loan_statistics::table)
+            .values(&stats)
+            .execute(conn)?;
+
+        Ok(diesel::update(
+            loans::table
+                .filter(loans::member_id.eq(member_id))
+                .filter(loans::status.eq(LoanStatus::Returned)),
+        )
+        .set(loans::member_id.eq(None::<uuid::Uuid>))
+        .execute(conn)?)
+    })
+}
+
+/// Extends a loan by the loan period of its circulation rule, counted from the
+/// current due date or from today if the loan is already late.
+///
+/// Refuses with every [`RenewalRefusal`] that applies, so the borrower learns
+/// all of them at once.
+pub async fn renew_loan(id: uuid::Uuid, conn: &mut PgConnection) -> Result<NaiveDate> {
+    with_retry(conn, |conn| {
+        conn.transaction(|conn| {
+            // locked so concurrent renewals are counted one after the other
+            let loan = lock_loan(id, conn)?;
+            let member_id = match (loan.status, loan.member_id) {
+                (LoanStatus::Open | LoanStatus::Overdue, Some(member_id)) => member_id,
+                _ => return Err(LibError::RenewalRefused(vec![RenewalRefusal::NotOpen]).into()),
+            };
+            check_member_blocks(member_id, conn)?;
+
+            let member = get_member(member_id, conn)?
+                .ok_or_else(|| LibError::DbError(format!("member {member_id} not found")))?;
+            let book = get_book(loan.book_id, conn)?.ok_or(NotFound)?;
+            let rule = require_rule(
+                &LoanContext {
+                    tier: member.tier,
+                    item_type: book.item_type,
+                    branch: book.branch.clone(),
+                },
+                conn,
+            )?;
+
+            let today = chrono::Utc::now().date_naive();
+            let mut reasons = Vec::new();
+            if loan.renewal_count >= rule.max_renewals {
+                reasons.push(RenewalRefusal::MaxRenewalsReached {
+                    max_renewals: rule.max_renewals,
+                });
+            }
+            let days_overdue = (today - loan.due_date).num_days();
+            if days_overdue > rule.renewal_overdue_days.into() {
+                reasons.push(RenewalRefusal::TooOverdue {
+                    days_overdue,
+                    limit: rule.renewal_overdue_days,
+                });
+            }
+            let waiting = count_waiting(loan.book_id, member_id, conn)?;
+            if waiting > 0 {
+                reasons.push(RenewalRefusal::OnHold { waiting });
+            }
+            if loan.recalled_at.is_some() {
+                reasons.push(RenewalRefusal::Recalled);
+            }
+
+            let mut due_date = next_open_day(
+                &book.branch,
+                loan.due_date.max(today) + chrono::Duration::days(rule.loan_days.into()),
+                conn,
+            )?;
+            // a copy borrowed from a partner has to be back before the partner
+            // wants it, as at checkout
+            if let Some(partner_due_date) =
+                get_borrowed_copy(loan.book_id, conn)?.and_then(|request| request.due_date)
+            {
+                due_date = due_date.min(partner_due_date);
+                if due_date <= loan.due_date {
+                    reasons.push(RenewalRefusal::PartnerDueDate { partner_due_date });
+                }
+            }
+            if !reasons.is_empty() {
+                return Err(LibError::RenewalRefused(reasons).into());
+            }
+
+            diesel::update(loans::table.filter(loans::loan_id.eq(id)))
+                .set((
+                    loans::due_date.eq(due_date),
+                    loans::renewal_count.eq(loans::renewal_count + 1),
+                    loans::status.eq(LoanStatus::Open),
+                ))
+                .execute(conn)
+                .with_context(|| LibError::DbError(format!("failed to renew loan {id}")))?;
+
+            Ok(due_date)
+        })
+    })
 }
 
 pub async fn get_loan(id: uuid::Uuid, conn: &mut PgConnection) -> Result<Option<Loan>> {
@@ -212,9 +989,215 @@ pub fn update_loan_return_date(
     diesel::update(loans::table.filter(loans::loan_id.eq(id)))
         .set(loans::return_date.eq(&new_val))
         .execute(conn)
-        .with_context(|| {
-            LibError::ActixError(
-                ErrorInternalServerError(format!("failed to update book {id} status")).to_string(),
-            )
-        })
+        .with_context(|| LibError::DbError(format!("failed to update book {id} status")))
+}
+
+/// Every loan of a member, newest first.
+pub async fn get_loan_history(
+    member_id: uuid::Uuid,
+    conn: &mut PgConnection,
+) -> Result<Vec<MemberLoan>> {
+    Ok(loans::table
+        .inner_join(books::table)
+        .filter(loans::member_id.eq(member_id))
+        .select((
+            loans::loan_id,
+            loans::book_id,
+            books::title,
+            loans::loan_date,
+            loans::due_date,
+            loans::return_date,
+            loans::status,
+        ))
+        .order((loans::loan_date.desc(), loans::due_date.desc()))
+        .load(conn)?)
+}
+
+/// Lists the loans of a member, newest first.
+///
+/// Filtering by [`LoanStatus::Overdue`] also matches open loans whose due date
+/// has passed, since nothing marks those as overdue on its own; the summary
+/// counts them the same way. It always covers every loan of the member,
+/// regardless of the filter.
+pub async fn get_member_loans(
+    member_id: uuid::Uuid,
+    status: Option<LoanStatus>,
+    pagination: Pagination,
+    conn: &mut PgConnection,
+) -> Result<MemberLoans> {
+    let today = chrono::Utc::now().date_naive();
+
+    let mut query = loans::table
+        .inner_join(books::table)
+        .filter(loans::member_id.eq(member_id))
+        .select((
+            loans::loan_id,
+            loans::book_id,
+            books::title,
+            loans::loan_date,
+            loans::due_date,
+            loans::return_date,
+            loans::status,
+        ))
+        .order((loans::loan_date.desc(), loans::due_date.desc()))
+        .into_boxed();
+
+    query = match status {
+        Some(LoanStatus::Overdue) => query.filter(
+            loans::status.eq(LoanStatus::Overdue).or(loans::status
+                .eq(LoanStatus::Open)
+                .and(loans::due_date.lt(today))),
+        ),
+        Some(status) => query.filter(loans::status.eq(status)),
+        None => query,
+    };
+
+    let rows = query
+        .limit(pagination.per_page())
+        .offset(pagination.offset())
+        .load::<MemberLoan>(conn)?;
+
+    let counts: Vec<(LoanStatus, i64)> = loans::table
+        .filter(loans::member_id.eq(member_id))
+        .group_by(loans::status)
+        .select((loans::status, diesel::dsl::count_star()))
+        .load(conn)?;
+    let late: i64 = loans::table
+        .filter(loans::member_id.eq(member_id))
+        .filter(loans::status.eq(LoanStatus::Open))
+        .filter(loans::due_date.lt(today))
+        .count()
+        .get_result(conn)?;
+
+    let mut summary = LoanSummary::default();
+    for (status, count) in counts {
+        summary.total += count;
+        match status {
+            LoanStatus::Open => summary.open += count,
+            LoanStatus::Returned => summary.returned += count,
+            LoanStatus::Overdue => summary.overdue += count,
+            LoanStatus::Lost => summary.lost += count,
+            LoanStatus::ClaimsReturned => summary.claims_returned += count,
+        }
+    }
+    summary.open -= late;
+    summary.overdue += late;
+
+    Ok(MemberLoans {
+        member_id,
+        page: pagination.page(),
+        per_page: pagination.per_page(),
+        summary,
+        loans: rows,
+    })
+}
+
+type LoanSearchQuery<'a> =
+    IntoBoxed<'a, LeftJoin<InnerJoin<loans::table, books::table>, members::table>, Pg>;
+
+/// The loans matching `search`, joined to their book and borrower. Open loans
+/// past their due date match an overdue filter too, as in [`get_member_loans`].
+fn search_query<'a>(search: &LoanSearch, today: NaiveDate) -> LoanSearchQuery<'a> {
+    let mut query = loans::table
+        .inner_join(books::table)
+        .left_join(members::table)
+        .into_boxed();
+
+    query = match search.status {
+        Some(LoanStatus::Overdue) => query.filter(
+            loans::status.eq(LoanStatus::Overdue).or(loans::status
+                .eq(LoanStatus::Open)
+                .and(loans::due_date.lt(today))),
+        ),
+        Some(status) => query.filter(loans::status.eq(status)),
+        None => query,
+    };
+    if let Some(member_id) = search.member_id {
+        query = query.filter(loans::member_id.eq(member_id));
+    }
+    if let Some(book_id) = search.book_id {
+        query = query.filter(loans::book_id.eq(book_id));
+    }
+    if let Some(loan_from) = search.loan_from {
+        query = query.filter(loans::loan_date.ge(loan_from));
+    }
+    if let Some(loan_to) = search.loan_to {
+        query = query.filter(loans::loan_date.le(loan_to));
+    }
+    if let Some(due_from) = search.due_from {
+        query = query.filter(loans::due_date.ge(due_from));
+    }
+    if let Some(due_to) = search.due_to {
+        query = query.filter(loans::due_date.le(due_to));
+    }
+    if let Some(days) = search.due_within_days {
+        query = query
+            .filter(loans::status.eq(LoanStatus::Open))
+            .filter(loans::due_date.between(today, today + chrono::Duration::days(days)));
+    }
+    query
+}
+
+/// One page of the loans matching `search`, soonest due first.
+pub async fn search_loans(
+    search: &LoanSearch,
+    pagination: Pagination,
+    conn: &mut PgConnection,
+) -> Result<LoanPage> {
+    search.validate()?;
+    let today = chrono::Utc::now().date_naive();
+    let total: i64 = search_query(search, today).count().get_result(conn)?;
+    let loans = search_query(search, today)
+        .select(loan_listing_columns())
+        .order((loans::due_date.asc(), loans::loan_id.asc()))
+        .limit(pagination.per_page())
+        .offset(pagination.offset())
+        .load(conn)?;
+    Ok(LoanPage {
+        page: pagination.page(),
+        per_page: pagination.per_page(),
+        total,
+        loans,
+    })
+}
+
+/// Every loan matching `search`, for reports.
+pub async fn export_loans(
+    search: &LoanSearch,
+    conn: &mut PgConnection,
+) -> Result<Vec<LoanListing>> {
+    search.validate()?;
+    let today = chrono::Utc::now().date_naive();
+    Ok(search_query(search, today)
+        .select(loan_listing_columns())
+        .order((loans::due_date.asc(), loans::loan_id.asc()))
+        .load(conn)?)
+}
+
+type LoanListingColumns = (
+    loans::loan_id,
+    loans::book_id,
+    books::title,
+    loans::member_id,
+    diesel::helper_types::Nullable<members::name>,
+    loans::loan_date,
+    loans::due_date,
+    loans::return_date,
+    loans::status,
+    loans::renewal_count,
+);
+
+fn loan_listing_columns() -> LoanListingColumns {
+    (
+        loans::loan_id,
+        loans::book_id,
+        books::title,
+        loans::member_id,
+        members::name.nullable(),
+        loans::loan_date,
+        loans::due_date,
+        loans::return_date,
+        loans::status,
+        loans::renewal_count,
+    )
 }
@@ -0,0 +1,255 @@
+//! Checkouts and renewals racing each other against the database in
+//! `DATABASE_URL`. The tests are skipped when it is not set.
+
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+};
+
+use diesel::{Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use uuid::Uuid;
+
+use super::models::{create_loan, renew_loan, LoanStatus, NewLoan};
+use crate::schema::{books, loans, member_relationships, members};
+
+fn connect() -> Option<PgConnection> {
+    dotenvy::dotenv().ok();
+    let url = std::env::var("DATABASE_URL").ok()?;
+    Some(PgConnection::establish(&url).unwrap_or_else(|e| panic!("cannot connect to {url}: {e}")))
+}
+
+fn add_member(conn: &mut PgConnection) -> Uuid {
+    diesel::insert_into(members::table)
+        .values((
+            members::name.eq("Concurrent Reader"),
+            members::email.eq(format!("{}@example.org", Uuid::new_v4())),
+            members::privilege.eq(false),
+            members::borrowed.eq(0),
+        ))
+        .returning(members::member_id)
+        .get_result(conn)
+        .unwrap()
+}
+
+fn add_book(conn: &mut PgConnection) -> Uuid {
+    diesel::insert_into(books::table)
+        .values((
+            books::title.eq("Contested Copy"),
+            books::author.eq("Anonymous"),
+            books::publication_year.eq(2000),
+            books::availability_status.eq(true),
+        ))
+        .returning(books::book_id)
+        .get_result(conn)
+        .unwrap()
+}
+
+fn borrowed(member_id: Uuid, conn: &mut PgConnection) -> i32 {
+    members::table
+        .find(member_id)
+        .select(members::borrowed)
+        .first(conn)
+        .unwrap()
+}
+
+/// Checks `book_id` out to every member at the same moment, one connection
+/// each, and returns how many checkouts succeeded.
+fn race(book_id: Uuid, member_ids: &[Uuid]) -> usize {
+    let barrier = Arc::new(Barrier::new(member_ids.len()));
+    let checkouts: Vec<_> = member_ids
+        .iter()
+        .map(|&member_id| {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let mut conn = connect().unwrap();
+                let payload = NewLoan {
+                    member_id,
+                    book_id,
+                    due_date: None,
+                };
+                barrier.wait();
+                actix_rt::System::new().block_on(create_loan(&payload, &mut conn))
+            })
+        })
+        .collect();
+    checkouts
+        .into_iter()
+        .map(|checkout| checkout.join().unwrap())
+        .filter(Result::is_ok)
+        .count()
+}
+
+/// Renews `loan_id` `times` times at the same moment and returns how many
+/// renewals succeeded.
+fn race_renewals(loan_id: Uuid, times: usize) -> usize {
+    let barrier = Arc::new(Barrier::new(times));
+    let renewals: Vec<_> = (0..times)
+        .map(|_| {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let mut conn = connect().unwrap();
+                barrier.wait();
+                actix_rt::System::new().block_on(renew_loan(loan_id, &mut conn))
+            })
+        })
+        .collect();
+    renewals
+        .into_iter()
+        .map(|renewal| renewal.join().unwrap())
+        .filter(Result::is_ok)
+        .count()
+}
+
+fn open_loans(book_id: Uuid, conn: &mut PgConnection) -> i64 {
+    loans::table
+        .filter(loans::book_id.eq(book_id))
+        .filter(loans::status.eq_any([LoanStatus::Open, LoanStatus::Overdue]))
+        .count()
+        .get_result(conn)
+        .unwrap()
+}
+
+fn clean_up(book_id: Uuid, member_ids: &[Uuid], conn: &mut PgConnection) {
+    diesel::delete(loans::table.filter(loans::book_id.eq(book_id)))
+        .execute(conn)
+        .unwrap();
+    diesel::delete(books::table.find(book_id))
+        .execute(conn)
+        .unwrap();
+    diesel::delete(members::table.filter(members::member_id.eq_any(member_ids)))
+        .execute(conn)
+        .unwrap();
+}
+
+#[test]
+fn concurrent_checkouts_by_one_member_lend_the_book_once() {
+    let Some(mut conn) = connect() else {
+        return;
+    };
+    let member_id = add_member(&mut conn);
+    let book_id = add_book(&mut conn);
+
+    let succeeded = race(book_id, &[member_id, member_id]);
+    let loans = open_loans(book_id, &mut conn);
+    let borrowed = borrowed(member_id, &mut conn);
+    clean_up(book_id, &[member_id], &mut conn);
+
+    assert_eq!(succeeded, 1);
+    assert_eq!(loans, 1);
+    assert_eq!(borrowed, 1);
+}
+
+#[test]
+fn concurrent_checkouts_by_different_members_lend_the_book_once() {
+    let Some(mut conn) = connect() else {
+        return;
+    };
+    let member_ids: Vec<Uuid> = (0..4).map(|_| add_member(&mut conn)).collect();
+    let book_id = add_book(&mut conn);
+
+    let succeeded = race(book_id, &member_ids);
+    let loans = open_loans(book_id, &mut conn);
+    let borrowed: i32 = member_ids.iter().map(|&id| borrowed(id, &mut conn)).sum();
+    clean_up(book_id, &member_ids, &mut conn);
+
+    assert_eq!(succeeded, 1);
+    assert_eq!(loans, 1);
+    assert_eq!(borrowed, 1);
+}
+
+#[test]
+fn concurrent_renewals_stop_at_max_renewals() {
+    let Some(mut conn) = connect() else {
+        return;
+    };
+    let member_id = add_member(&mut conn);
+    let book_id = add_book(&mut conn);
+    let payload = NewLoan {
+        member_id,
+        book_id,
+        due_date: None,
+    };
+    let loan_id = actix_rt::System::new()
+        .block_on(create_loan(&payload, &mut conn))
+        .unwrap();
+    // one renewal short of the default limit of two
+    diesel::update(loans::table.find(loan_id))
+        .set(loans::renewal_count.eq(1))
+        .execute(&mut conn)
+        .unwrap();
+
+    let succeeded = race_renewals(loan_id, 4);
+    let renewal_count: i32 = loans::table
+        .find(loan_id)
+        .select(loans::renewal_count)
+        .first(&mut conn)
+        .unwrap();
+    clean_up(book_id, &[member_id], &mut conn);
+
+    assert_eq!(succeeded, 1);
+    assert_eq!(renewal_count, 2);
+}
+
+#[test]
+fn concurrent_checkouts_in_one_household_respect_its_limit() {
+    let Some(mut conn) = connect() else {
+        return;
+    };
+    let guardian_id = add_member(&mut conn);
+    diesel::update(members::table.find(guardian_id))
+        .set(members::household_limit.eq(1))
+        .execute(&mut conn)
+        .unwrap();
+    let dependent_ids: Vec<Uuid> = (0..3).map(|_| add_member(&mut conn)).collect();
+    for &dependent_id in &dependent_ids {
+        diesel::insert_into(member_relationships::table)
+            .values((
+                member_relationships::guardian_id.eq(guardian_id),
+                member_relationships::dependent_id.eq(dependent_id),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+    }
+    let book_ids: Vec<Uuid> = dependent_ids.iter().map(|_| add_book(&mut conn)).collect();
+
+    let barrier = Arc::new(Barrier::new(dependent_ids.len()));
+    let checkouts: Vec<_> = dependent_ids
+        .iter()
+        .zip(&book_ids)
+        .map(|(&member_id, &book_id)| {
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let mut conn = connect().unwrap();
+                let payload = NewLoan {
+                    member_id,
+                    book_id,
+                    due_date: None,
+                };
+                barrier.wait();
+                actix_rt::System::new().block_on(create_loan(&payload, &mut conn))
+            })
+        })
+        .collect();
+    let succeeded = checkouts
+        .into_iter()
+        .map(|checkout| checkout.join().unwrap())
+        .filter(Result::is_ok)
+        .count();
+    let borrowed: i32 = dependent_ids
+        .iter()
+        .map(|&id| borrowed(id, &mut conn))
+        .sum();
+
+    diesel::delete(
+        member_relationships::table.filter(member_relationships::guardian_id.eq(guardian_id)),
+    )
+    .execute(&mut conn)
+    .unwrap();
+    for (&member_id, &book_id) in dependent_ids.iter().zip(&book_ids) {
+        clean_up(book_id, &[member_id], &mut conn);
+    }
+    clean_up(Uuid::nil(), &[guardian_id], &mut conn);
+
+    assert_eq!(succeeded, 1);
+    assert_eq!(borrowed, 1);
+}
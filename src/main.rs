@@ -3,11 +3,26 @@
 use actix_web::{middleware, web::Data, App, HttpServer, Responder};
 use diesel::{r2d2::ConnectionManager, PgConnection};
 
+mod api_keys;
+mod auth;
+mod blocks;
 mod books;
+mod circulation;
+mod closures;
 mod db;
 mod errors;
+mod fines;
+mod holds;
+mod households;
+mod ill;
 mod loans;
 mod members;
+mod notes;
+mod notices;
+mod overdue;
+mod pagination;
+mod policy;
+mod receipts;
 mod schema;
 
 #[actix_web::get("/")]
@@ -27,24 +42,114 @@ async fn main() -> std::io::Result<()> {
     let pool = r2d2::Pool::builder()
         .build(manager)
         .expect("Failed to create pool");
+    let auth_config = auth::models::AuthConfig::from_env();
+    let jwt_config = auth::models::JwtConfig::from_env().expect("Invalid JWT configuration");
+    auth::models::bootstrap_admin(&mut db::establish_connection())
+        .expect("Failed to create the bootstrap administrator");
+    let notice_config =
+        notices::models::NoticeConfig::from_env().expect("Invalid notice configuration");
+    if let Some(interval) = overdue::models::SweeperConfig::from_env().interval {
+        actix_rt::spawn(overdue::models::run_sweeper(
+            interval,
+            notice_config.clone(),
+        ));
+    }
 
     // start server
     HttpServer::new(move || {
         App::new()
             .app_data(Data::new(pool.clone()))
+            .app_data(Data::new(auth_config.clone()))
+            .app_data(Data::new(notice_config.clone()))
+            .wrap(auth::middleware::Authenticate::new(jwt_config.clone()))
             .wrap(middleware::Logger::default())
             .service(hello)
+            .service(auth::handlers::login)
+            .service(auth::handlers::logout)
+            .service(auth::handlers::current_session)
+            .service(auth::handlers::change_credentials)
+            .service(auth::handlers::revoke_sessions)
+            .service(api_keys::handlers::create_api_key)
+            .service(api_keys::handlers::fetch_api_keys)
+            .service(api_keys::handlers::remove_api_key)
             .service(books::handlers::add_book)
             .service(books::handlers::fetch_book)
             .service(books::handlers::change_book)
             .service(books::handlers::remove_book)
             .service(members::handlers::create_member)
+            // registered ahead of /members/{member_id} so the literal path wins
+            .service(members::handlers::fetch_duplicates)
+            .service(members::handlers::merge)
+            .service(members::handlers::fetch_merges)
+            .service(members::handlers::import_members_csv)
             .service(members::handlers::fetch_member)
             .service(members::handlers::change_member)
             .service(members::handlers::remove_member)
+            .service(members::handlers::export_member_data)
+            .service(members::handlers::anonymize)
+            .service(members::handlers::change_role)
+            .service(members::handlers::change_privacy)
             .service(loans::handlers::new_loan)
+            .service(loans::handlers::new_loans)
+            .service(loans::handlers::fetch_loans)
+            .service(loans::handlers::export_loans_csv)
+            .service(receipts::handlers::batch_receipt)
+            .service(receipts::handlers::email_receipt)
+            .service(receipts::handlers::loan_receipt)
             .service(loans::handlers::fetch_loan)
             .service(loans::handlers::close_loan)
+            .service(loans::handlers::renew)
+            .service(loans::handlers::lost)
+            .service(loans::handlers::claims_returned)
+            .service(loans::handlers::recall)
+            .service(loans::handlers::fetch_member_loans)
+            .service(loans::handlers::export_loan_history)
+            .service(circulation::handlers::checkin)
+            .service(circulation::handlers::checkout)
+            .service(households::handlers::add_dependent)
+            .service(households::handlers::fetch_dependents)
+            .service(households::handlers::fetch_guardians)
+            .service(households::handlers::remove_dependent)
+            .service(households::handlers::fetch_dependent_loans)
+            .service(households::handlers::renew_dependent_loan)
+            .service(blocks::handlers::fetch_blocks)
+            .service(blocks::handlers::add_block)
+            .service(blocks::handlers::change_block)
+            .service(blocks::handlers::remove_block)
+            .service(notes::handlers::fetch_notes)
+            .service(notes::handlers::add_note)
+            .service(notes::handlers::remove_note)
+            .service(policy::handlers::fetch_rules)
+            .service(policy::handlers::create_rule)
+            .service(policy::handlers::change_rule)
+            .service(policy::handlers::remove_rule)
+            .service(policy::handlers::resolve)
+            .service(closures::handlers::fetch_closures)
+            .service(closures::handlers::create_closure)
+            .service(closures::handlers::change_closure)
+            .service(closures::handlers::remove_closure)
+            .service(holds::handlers::add_hold)
+            .service(holds::handlers::fetch_book_holds)
+            .service(holds::handlers::remove_hold)
+            .service(holds::handlers::fetch_member_holds)
+            .service(holds::handlers::expire)
+            .service(fines::handlers::fetch_account)
+            .service(fines::handlers::add_payment)
+            .service(fines::handlers::add_waiver)
+            .service(overdue::handlers::run_sweep)
+            .service(notices::handlers::send)
+            .service(notices::handlers::outbox)
+            .service(notices::handlers::fetch_member_notices)
+            .service(ill::handlers::add_request)
+            .service(ill::handlers::fetch_request)
+            .service(ill::handlers::fetch_queue)
+            .service(ill::handlers::fetch_messages)
+            .service(ill::handlers::send)
+            .service(ill::handlers::receive)
+            .service(ill::handlers::ship_return)
+            .service(ill::handlers::cancel)
+            .service(ill::handlers::change_status)
+            .service(ill::handlers::exchange)
     })
     .bind("127.0.0.1:9090")?
     .run()
@@ -1,2 +1,4 @@
 pub mod handlers;
+pub mod import;
+pub mod merge;
 pub mod models;
@@ -1,29 +1,52 @@
-use crate::db::establish_connection;
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+    errors::error_response,
+};
 
-use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use actix_web::{delete, get, http::header, post, put, web, HttpResponse, Responder};
 
-use super::models::{add_member, delete_member, get_member, update_member, NewMember};
+use super::import::{import_members, ImportOptions};
+use super::merge::{find_duplicates, get_merges, merge_members, MergeRequest};
+use super::models::{
+    add_member, anonymize_member, delete_member, export_member, get_member, update_member,
+    update_member_role, update_privacy, NewMember, PrivacySettings, RoleRequest,
+};
 
 #[post("/members/new")]
-async fn create_member(payload: web::Json<NewMember>) -> impl Responder {
+async fn create_member(principal: Principal, payload: web::Json<NewMember>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageMembers) {
+        return denied;
+    }
     let mut conn = establish_connection();
-    match add_member(&payload.name, &payload.email, payload.privilege, &mut conn) {
+    match add_member(payload.into_inner(), &mut conn) {
         Ok(member_id) => HttpResponse::Ok().json(member_id),
-        Err(e) => HttpResponse::InternalServerError().json(format!("failed to create member {e}")),
+        Err(e) => error_response(&e),
     }
 }
 
 #[delete("/members/{member_id}")]
-async fn remove_member(id: web::Path<uuid::Uuid>) -> impl Responder {
+async fn remove_member(principal: Principal, id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::EraseMembers) {
+        return denied;
+    }
     let mut conn = establish_connection();
+    match get_member(*id, &mut conn) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(format!("member {id} not found")),
+        Err(e) => return error_response(&e),
+    }
     match delete_member(*id, &mut conn) {
         Ok(_) => HttpResponse::Ok().json(format!("deleted {id}")),
-        Err(e) => HttpResponse::NotFound().json(format!("{e}")),
+        Err(e) => error_response(&e),
     }
 }
 
 #[get("/members/{member_id}")]
-async fn fetch_member(id: web::Path<uuid::Uuid>) -> impl Responder {
+async fn fetch_member(principal: Principal, id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require_for(*id, Permission::ReadMembers) {
+        return denied;
+    }
     let mut conn = establish_connection();
     match get_member(*id, &mut conn) {
         Ok(Some(member)) => HttpResponse::Ok().json(member),
@@ -34,9 +57,13 @@ async fn fetch_member(id: web::Path<uuid::Uuid>) -> impl Responder {
 
 #[put("/members/{member_id}")]
 async fn change_member(
+    principal: Principal,
     id: web::Path<uuid::Uuid>,
     member_request: web::Json<NewMember>,
 ) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageMembers) {
+        return denied;
+    }
     let mut conn = establish_connection();
     match update_member(*id, member_request.into_inner(), &mut conn) {
         Ok(update) => {
@@ -46,6 +73,125 @@ async fn change_member(
                 HttpResponse::NotFound().finish()
             }
         }
+        Err(e) => error_response(&e),
+    }
+}
+
+#[get("/members/{member_id}/export")]
+async fn export_member_data(principal: Principal, id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require_for(*id, Permission::ExportMembers) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match export_member(*id, &mut conn).await {
+        Ok(Some(export)) => HttpResponse::Ok()
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"member-{id}.json\""),
+            ))
+            .json(export),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(format!("failed to export member {e}")),
+    }
+}
+
+#[post("/members/{member_id}/anonymize")]
+async fn anonymize(principal: Principal, id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::EraseMembers) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match anonymize_member(*id, &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => error_response(&e),
+    }
+}
+
+#[put("/members/{member_id}/role")]
+async fn change_role(
+    principal: Principal,
+    id: web::Path<uuid::Uuid>,
+    payload: web::Json<RoleRequest>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageRoles) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match update_member_role(*id, payload.role, &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(format!("failed to update member {e}")),
+    }
+}
+
+#[put("/members/{member_id}/privacy")]
+async fn change_privacy(
+    principal: Principal,
+    id: web::Path<uuid::Uuid>,
+    payload: web::Json<PrivacySettings>,
+) -> impl Responder {
+    if let Err(denied) = principal.require_for(*id, Permission::ManagePrivacy) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match update_privacy(*id, &payload, &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
         Err(e) => HttpResponse::InternalServerError().json(format!("failed to update member {e}")),
     }
 }
+
+#[post("/members/import.csv")]
+async fn import_members_csv(
+    principal: Principal,
+    options: web::Query<ImportOptions>,
+    body: web::Bytes,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageMembers) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match import_members(&body, &options, &mut conn) {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => error_response(&e),
+    }
+}
+
+#[get("/members/duplicates")]
+async fn fetch_duplicates(principal: Principal) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageMembers) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match find_duplicates(&mut conn) {
+        Ok(clusters) => HttpResponse::Ok().json(clusters),
+        Err(e) => {
+            HttpResponse::InternalServerError().json(format!("failed to find duplicates {e}"))
+        }
+    }
+}
+
+#[post("/members/merge")]
+async fn merge(principal: Principal, payload: web::Json<MergeRequest>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::EraseMembers) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match merge_members(&payload, principal.member_id, &mut conn) {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => HttpResponse::BadRequest().json(format!("failed to merge members {e}")),
+    }
+}
+
+#[get("/members/{member_id}/merges")]
+async fn fetch_merges(principal: Principal, id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageMembers) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_merges(*id, &mut conn) {
+        Ok(merges) => HttpResponse::Ok().json(merges),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
@@ -0,0 +1,439 @@
+use actix_web::error::ErrorBadRequest;
+use anyhow::Result;
+use chrono::NaiveDate;
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{db::lower, errors::LibError, schema::members};
+
+use super::models::{is_known_tier, Member, NewMember, DEFAULT_TIER};
+
+const DEFAULT_EXPIRY_GRACE_DAYS: i64 = 30;
+const MAX_EXPIRY_GRACE_DAYS: i64 = 3650;
+
+/// One row of the registrar file. Columns are matched by header name, and
+/// every column but `name` may be left out.
+#[derive(Debug, Deserialize)]
+struct ImportRow {
+    external_id: Option<String>,
+    name: String,
+    email: Option<String>,
+    tier: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportOptions {
+    /// Report what would change without writing anything.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Days from today until members missing from the file expire, at most
+    /// ten years.
+    pub expiry_grace_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RowAction {
+    Created,
+    Updated,
+    Unchanged,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RowReport {
+    /// Line of the file, counting the header as line 1.
+    pub line: u64,
+    pub external_id: Option<String>,
+    pub email: Option<String>,
+    pub action: RowAction,
+    pub member_id: Option<Uuid>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExpiringMember {
+    pub member_id: Uuid,
+    pub external_id: Option<String>,
+    pub name: String,
+    pub expires_on: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub failed: usize,
+    pub rows: Vec<RowReport>,
+    /// Registrar-managed members that are not in the file and were flagged to
+    /// expire.
+    pub missing: Vec<ExpiringMember>,
+}
+
+/// Marker used to roll back the transaction of a dry run.
+#[derive(Debug, thiserror::Error)]
+#[error("dry run")]
+struct DryRun(ImportReport);
+
+fn normalize(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Upserts the members of a registrar CSV file, matching rows by external id
+/// first and by email otherwise.
+///
+/// Every row is applied in its own savepoint, so a bad row is reported without
+/// stopping the import. Members with an external id that are not in the file
+/// get an expiry date, unless they already expire sooner. A dry run does all of
+/// this inside a transaction that is rolled back, so the report is exactly what
+/// a real run would have done.
+pub fn import_members(
+    csv_data: &[u8],
+    options: &ImportOptions,
+    conn: &mut PgConnection,
+) -> Result<ImportReport> {
+    if options
+        .expiry_grace_days
+        .is_some_and(|days| !(0..=MAX_EXPIRY_GRACE_DAYS).contains(&days))
+    {
+        return Err(LibError::ActixError(
+            ErrorBadRequest(format!(
+                "expiry_grace_days must be between 0 and {MAX_EXPIRY_GRACE_DAYS}"
+            ))
+            .to_string(),
+        )
+        .into());
+    }
+    let result = conn.transaction::<_, anyhow::Error, _>(|conn| {
+        let report = apply_import(csv_data, options, conn)?;
+        if options.dry_run {
+            Err(DryRun(report).into())
+        } else {
+            Ok(report)
+        }
+    });
+
+    match result {
+        Err(e) if e.is::<DryRun>() => match e.downcast::<DryRun>() {
+            Ok(DryRun(report)) => Ok(report),
+            Err(e) => Err(e),
+        },
+        other => other,
+    }
+}
+
+fn apply_import(
+    csv_data: &[u8],
+    options: &ImportOptions,
+    conn: &mut PgConnection,
+) -> Result<ImportReport> {
+    let mut report = ImportReport {
+        dry_run: options.dry_run,
+        created: 0,
+        updated: 0,
+        unchanged: 0,
+        failed: 0,
+        rows: Vec::new(),
+        missing: Vec::new(),
+    };
+    let mut seen: Vec<Uuid> = Vec::new();
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(csv_data);
+    let headers = reader.headers()?.clone();
+    for record in reader.records() {
+        let line = match &record {
+            Ok(record) => record.position().map_or(0, |p| p.line()),
+            Err(e) => e.position().map_or(0, |p| p.line()),
+        };
+        let row = record.and_then(|record| record.deserialize::<ImportRow>(Some(&headers)));
+        let row_report = match row {
+            Ok(row) => {
+                let external_id = normalize(row.external_id.clone());
+                let email = normalize(row.email.clone());
+                match conn.transaction(|conn| upsert_row(row, conn)) {
+                    Ok((action, member_id)) => {
+                        seen.push(member_id);
+                        RowReport {
+                            line: 0,
+                            external_id,
+                            email,
+                            action,
+                            member_id: Some(member_id),
+                            message: None,
+                        }
+                    }
+                    Err(e) => RowReport {
+                        line: 0,
+                        external_id,
+                        email,
+                        action: RowAction::Failed,
+                        member_id: None,
+                        message: Some(e.to_string()),
+                    },
+                }
+            }
+            Err(e) => RowReport {
+                line: 0,
+                external_id: None,
+                email: None,
+                action: RowAction::Failed,
+                member_id: None,
+                message: Some(e.to_string()),
+            },
+        };
+
+        match row_report.action {
+            RowAction::Created => report.created += 1,
+            RowAction::Updated => report.updated += 1,
+            RowAction::Unchanged => report.unchanged += 1,
+            RowAction::Failed => report.failed += 1,
+        }
+        report.rows.push(RowReport { line, ..row_report });
+    }
+
+    let grace_days = options
+        .expiry_grace_days
+        .unwrap_or(DEFAULT_EXPIRY_GRACE_DAYS);
+    let expires_on = chrono::Utc::now().date_naive() + chrono::Duration::days(grace_days);
+    report.missing = flag_missing(&seen, expires_on, conn)?;
+
+    Ok(report)
+}
+
+fn find_match(
+    external_id: Option<&str>,
+    email: Option<&str>,
+    conn: &mut PgConnection,
+) -> Result<Option<Member>> {
+    if let Some(external_id) = external_id {
+        let found = members::table
+            .filter(members::external_id.eq(external_id))
+            .first::<Member>(conn)
+            .optional()?;
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+    if let Some(email) = email {
+        let found: Vec<Member> = members::table
+            .filter(lower(members::email).eq(email.to_lowercase()))
+            .limit(2)
+            .load(conn)?;
+        if found.len() > 1 {
+            return Err(anyhow::anyhow!("several members share the email {email}"));
+        }
+        return Ok(found.into_iter().next());
+    }
+    Ok(None)
+}
+
+/// A row with its values trimmed, blanks taken as missing.
+#[derive(Debug, PartialEq, Eq)]
+struct CheckedRow {
+    name: String,
+    external_id: Option<String>,
+    email: Option<String>,
+    tier: Option<String>,
+}
+
+/// Checks what a row can be checked for without looking at the members.
+fn check_row(row: ImportRow) -> Result<CheckedRow> {
+    let name = row.name.trim().to_string();
+    if name.is_empty() {
+        return Err(anyhow::anyhow!("name is empty"));
+    }
+    let external_id = normalize(row.external_id);
+    let email = normalize(row.email);
+    let tier = normalize(row.tier);
+    if let Some(tier) = tier.as_deref().filter(|tier| !is_known_tier(tier)) {
+        return Err(anyhow::anyhow!("unknown tier {tier}"));
+    }
+    if external_id.is_none() && email.is_none() {
+        return Err(anyhow::anyhow!(
+            "row has neither an external id nor an email"
+        ));
+    }
+    Ok(CheckedRow {
+        name,
+        external_id,
+        email,
+        tier,
+    })
+}
+
+fn upsert_row(row: ImportRow, conn: &mut PgConnection) -> Result<(RowAction, Uuid)> {
+    let CheckedRow {
+        name,
+        external_id,
+        email,
+        tier,
+    } = check_row(row)?;
+
+    let Some(existing) = find_match(external_id.as_deref(), email.as_deref(), conn)? else {
+        if email.is_none() {
+            return Err(anyhow::anyhow!("a new member needs an email"));
+        }
+        let member_id = diesel::insert_into(members::table)
+            .values(&NewMember {
+                name,
+                email,
+                privilege: false,
+                borrowed: 0,
+                household_limit: None,
+                external_id,
+                tier: tier.unwrap_or_else(|| DEFAULT_TIER.to_string()),
+                expires_on: None,
+                card_number: None,
+                dependent: false,
+            })
+            .returning(members::member_id)
+            .get_result(conn)?;
+        return Ok((RowAction::Created, member_id));
+    };
+
+    if existing.anonymized_at.is_some() {
+        return Err(anyhow::anyhow!(
+            "member {} has been anonymized",
+            existing.member_id
+        ));
+    }
+
+    let email = email.or(existing.email.clone());
+    let external_id = external_id.or(existing.external_id.clone());
+    let tier = tier.unwrap_or(existing.tier.clone());
+    let unchanged = existing.name == name
+        && existing.email == email
+        && existing.external_id == external_id
+        && existing.tier == tier
+        && existing.expires_on.is_none();
+    if unchanged {
+        return Ok((RowAction::Unchanged, existing.member_id));
+    }
+
+    diesel::update(members::table.filter(members::member_id.eq(existing.member_id)))
+        .set((
+            members::name.eq(name),
+            members::email.eq(email),
+            members::external_id.eq(external_id),
+            members::tier.eq(tier),
+            members::expires_on.eq(None::<NaiveDate>),
+        ))
+        .execute(conn)?;
+    Ok((RowAction::Updated, existing.member_id))
+}
+
+fn flag_missing(
+    seen: &[Uuid],
+    expires_on: NaiveDate,
+    conn: &mut PgConnection,
+) -> Result<Vec<ExpiringMember>> {
+    let missing: Vec<Member> = members::table
+        .filter(members::external_id.is_not_null())
+        .filter(members::anonymized_at.is_null())
+        .filter(members::member_id.ne_all(seen))
+        .order(members::name)
+        .load(conn)?;
+
+    let mut flagged = Vec::with_capacity(missing.len());
+    for member in missing {
+        let expires_on = match member.expires_on {
+            Some(current) if current <= expires_on => current,
+            _ => {
+                diesel::update(members::table.filter(members::member_id.eq(member.member_id)))
+                    .set(members::expires_on.eq(expires_on))
+                    .execute(conn)?;
+                expires_on
+            }
+        };
+        flagged.push(ExpiringMember {
+            member_id: member.member_id,
+            external_id: member.external_id,
+            name: member.name,
+            expires_on: Some(expires_on),
+        });
+    }
+    Ok(flagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(csv_data: &str) -> Vec<Result<ImportRow, csv::Error>> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(csv_data.as_bytes());
+        let headers = reader.headers().unwrap().clone();
+        reader
+            .records()
+            .map(|record| record.and_then(|record| record.deserialize(Some(&headers))))
+            .collect()
+    }
+
+    fn check(csv_data: &str) -> Vec<Result<CheckedRow>> {
+        rows(csv_data)
+            .into_iter()
+            .map(|row| check_row(row?))
+            .collect()
+    }
+
+    fn message(checked: &Result<CheckedRow>) -> String {
+        checked.as_ref().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn values_are_trimmed_and_blanks_dropped() {
+        let checked = check("name,external_id,email,tier\n  Ann Reader , S-1 ,  , standard \n");
+        assert_eq!(
+            checked[0].as_ref().unwrap(),
+            &CheckedRow {
+                name: "Ann Reader".to_string(),
+                external_id: Some("S-1".to_string()),
+                email: None,
+                tier: Some("standard".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn columns_other_than_name_may_be_left_out() {
+        let checked = check("email,name\nann@example.org,Ann\n");
+        let row = checked[0].as_ref().unwrap();
+        assert_eq!(row.email.as_deref(), Some("ann@example.org"));
+        assert_eq!(row.external_id, None);
+        assert_eq!(row.tier, None);
+
+        let nameless = rows("email\nann@example.org\n");
+        assert!(nameless[0].is_err());
+    }
+
+    #[test]
+    fn rows_need_a_name_and_a_key() {
+        let checked = check("name,external_id,email\n ,S-1,\nAnn,,\nBob, , \n");
+        assert_eq!(message(&checked[0]), "name is empty");
+        assert_eq!(
+            message(&checked[1]),
+            "row has neither an external id nor an email"
+        );
+        assert_eq!(
+            message(&checked[2]),
+            "row has neither an external id nor an email"
+        );
+    }
+
+    #[test]
+    fn unknown_tiers_are_refused() {
+        let checked = check("name,external_id,tier\nAnn,S-1,wizard\nBob,S-2,\n");
+        assert_eq!(message(&checked[0]), "unknown tier wizard");
+        assert_eq!(checked[1].as_ref().unwrap().tier, None);
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::{Insertable, Queryable},
+    BoolExpressionMethods, Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+    Selectable, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    errors::LibError,
+    holds::models::{cancel_hold, ACTIVE},
+    loans::models::LoanStatus,
+    schema::{
+        api_keys, credentials, fines, holds, ill_requests, loans, member_blocks, member_merges,
+        member_notes, member_relationships, members, notices, sessions,
+    },
+};
+
+use super::models::{get_member, Member};
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateCluster {
+    /// Which normalized fields tie the members of the cluster together.
+    pub matched_on: Vec<&'static str>,
+    pub members: Vec<Member>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeRequest {
+    pub primary_id: Uuid,
+    pub duplicate_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MergeReport {
+    pub primary_id: Uuid,
+    pub merged: Vec<Uuid>,
+    pub loans_moved: usize,
+    pub borrowed: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = member_merges)]
+pub struct NewMerge {
+    pub primary_id: Uuid,
+    pub merged_member_id: Uuid,
+    pub merged_by: Option<Uuid>,
+    pub snapshot: serde_json::Value,
+    pub loans_moved: i32,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = member_merges)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MemberMerge {
+    pub merge_id: Uuid,
+    pub primary_id: Uuid,
+    pub merged_member_id: Uuid,
+    pub merged_by: Option<Uuid>,
+    pub merged_at: NaiveDateTime,
+    pub snapshot: serde_json::Value,
+    pub loans_moved: i32,
+}
+
+/// Lowercases a name and drops punctuation and word order, so "Smith, John"
+/// and "john smith" compare equal.
+fn normalize_name(name: &str) -> String {
+    let cleaned: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    let mut words: Vec<&str> = cleaned.split_whitespace().collect();
+    words.sort_unstable();
+    words.join(" ")
+}
+
+/// Lowercases an email and drops any `+tag` from the local part.
+fn normalize_email(email: &str) -> Option<String> {
+    let email = email.trim().to_lowercase();
+    let (local, domain) = email.split_once('@')?;
+    let local = local.split('+').next().unwrap_or(local);
+    Some(format!("{local}@{domain}"))
+}
+
+fn find_root(parents: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    parents[i] = root;
+    root
+}
+
+/// Groups members that share a normalized name or email. Anonymized members
+/// are left out.
+pub fn find_duplicates(conn: &mut PgConnection) -> Result<Vec<DuplicateCluster>> {
+    let all: Vec<Member> = members::table
+        .filter(members::anonymized_at.is_null())
+        .order(members::name)
+        .load(conn)?;
+
+    let mut parents: Vec<usize> = (0..all.len()).collect();
+    let mut matched: Vec<(bool, bool)> = vec![(false, false); all.len()];
+    let mut by_name: HashMap<String, usize> = HashMap::new();
+    let mut by_email: HashMap<String, usize> = HashMap::new();
+
+    for (i, member) in all.iter().enumerate() {
+        let name = normalize_name(&member.name);
+        if !name.is_empty() {
+            if let Some(&j) = by_name.get(&name) {
+                let (a, b) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                parents[a] = b;
+                matched[i].0 = true;
+                matched[j].0 = true;
+            } else {
+                by_name.insert(name, i);
+            }
+        }
+        if let Some(email) = member.email.as_deref().and_then(normalize_email) {
+            if let Some(&j) = by_email.get(&email) {
+                let (a, b) = (find_root(&mut parents, i), find_root(&mut parents, j));
+                parents[a] = b;
+                matched[i].1 = true;
+                matched[j].1 = true;
+            } else {
+                by_email.insert(email, i);
+            }
+        }
+    }
+
+    let mut clusters: HashMap<usize, (bool, bool, Vec<Member>)> = HashMap::new();
+    for (i, member) in all.into_iter().enumerate() {
+        let root = find_root(&mut parents, i);
+        let cluster = clusters.entry(root).or_insert((false, false, Vec::new()));
+        cluster.0 |= matched[i].0;
+        cluster.1 |= matched[i].1;
+        cluster.2.push(member);
+    }
+
+    let mut duplicates: Vec<DuplicateCluster> = clusters
+        .into_values()
+        .filter(|(_, _, members)| members.len() > 1)
+        .map(|(name, email, members)| DuplicateCluster {
+            matched_on: [(name, "name"), (email, "email")]
+                .into_iter()
+                .filter_map(|(hit, field)| hit.then_some(field))
+                .collect(),
+            members,
+        })
+        .collect();
+    duplicates.sort_by(|a, b| a.members[0].name.cmp(&b.members[0].name));
+    Ok(duplicates)
+}
+
+/// Folds duplicate accounts into `primary_id` in a single transaction.
+///
+/// Loans and everything else that points at a duplicate is moved over, the
+/// primary keeps its own details but picks up an email or external id it is
+/// missing, and `borrowed` is recomputed from the open loans. Each duplicate is
+/// recorded in `member_merges` with a snapshot of its row before it is
+/// deleted.
+pub fn merge_members(
+    request: &MergeRequest,
+    merged_by: Option<Uuid>,
+    conn: &mut PgConnection,
+) -> Result<MergeReport> {
+    let primary_id = request.primary_id;
+    conn.transaction(|conn| {
+        let mut primary = get_member(primary_id, conn)?
+            .ok_or_else(|| LibError::DbError(format!("member {primary_id} not found")))?;
+        let mut loans_moved = 0;
+
+        for &duplicate_id in &request.duplicate_ids {
+            if duplicate_id == primary_id {
+                return Err(LibError::DbError(format!(
+                    "member {primary_id} cannot be merged into itself"
+                ))
+                .into());
+            }
+            let duplicate = get_member(duplicate_id, conn)?
+                .ok_or_else(|| LibError::DbError(format!("member {duplicate_id} not found")))?;
+
+            let moved = diesel::update(loans::table.filter(loans::member_id.eq(duplicate_id)))
+                .set(loans::member_id.eq(primary_id))
+                .execute(conn)?;
+            loans_moved += moved;
+
+            move_relationships(duplicate_id, primary_id, conn)?;
+            move_holds(duplicate_id, primary_id, conn)?;
+            diesel::update(fines::table.filter(fines::member_id.eq(duplicate_id)))
+                .set(fines::member_id.eq(primary_id))
+                .execute(conn)?;
+            diesel::update(notices::table.filter(notices::member_id.eq(duplicate_id)))
+                .set(notices::member_id.eq(primary_id))
+                .execute(conn)?;
+            diesel::update(ill_requests::table.filter(ill_requests::member_id.eq(duplicate_id)))
+                .set(ill_requests::member_id.eq(primary_id))
+                .execute(conn)?;
+
+            diesel::update(member_blocks::table.filter(member_blocks::member_id.eq(duplicate_id)))
+                .set(member_blocks::member_id.eq(primary_id))
+                .execute(conn)?;
+            diesel::update(member_notes::table.filter(member_notes::member_id.eq(duplicate_id)))
+                .set(member_notes::member_id.eq(primary_id))
+                .execute(conn)?;
+            diesel::update(member_merges::table.filter(member_merges::primary_id.eq(duplicate_id)))
+                .set(member_merges::primary_id.eq(primary_id))
+                .execute(conn)?;
+
+            diesel::delete(sessions::table.filter(sessions::member_id.eq(duplicate_id)))
+                .execute(conn)?;
+            let primary_logins: i64 = credentials::table
+                .filter(credentials::member_id.eq(primary_id))
+                .count()
+                .get_result(conn)?;
+            if primary_logins == 0 {
+                diesel::update(credentials::table.filter(credentials::member_id.eq(duplicate_id)))
+                    .set(credentials::member_id.eq(primary_id))
+                    .execute(conn)?;
+            } else {
+                diesel::delete(credentials::table.filter(credentials::member_id.eq(duplicate_id)))
+                    .execute(conn)?;
+            }
+
+            // rows this member wrote as staff now belong to the surviving account
+            diesel::update(api_keys::table.filter(api_keys::created_by.eq(duplicate_id)))
+                .set(api_keys::created_by.eq(primary_id))
+                .execute(conn)?;
+            diesel::update(member_blocks::table.filter(member_blocks::created_by.eq(duplicate_id)))
+                .set(member_blocks::created_by.eq(primary_id))
+                .execute(conn)?;
+            diesel::update(member_notes::table.filter(member_notes::created_by.eq(duplicate_id)))
+                .set(member_notes::created_by.eq(primary_id))
+                .execute(conn)?;
+            diesel::update(fines::table.filter(fines::created_by.eq(duplicate_id)))
+                .set(fines::created_by.eq(primary_id))
+                .execute(conn)?;
+            diesel::update(member_merges::table.filter(member_merges::merged_by.eq(duplicate_id)))
+                .set(member_merges::merged_by.eq(primary_id))
+                .execute(conn)?;
+
+            diesel::insert_into(member_merges::table)
+                .values(&NewMerge {
+                    primary_id,
+                    merged_member_id: duplicate_id,
+                    merged_by,
+                    snapshot: serde_json::to_value(&duplicate)?,
+                    loans_moved: moved as i32,
+                })
+                .execute(conn)?;
+
+            diesel::delete(members::table.filter(members::member_id.eq(duplicate_id)))
+                .execute(conn)?;
+
+            if primary.email.is_none() {
+                primary.email = duplicate.email;
+            }
+            if primary.external_id.is_none() {
+                primary.external_id = duplicate.external_id;
+            }
+            if primary.card_number.is_none() {
+                primary.card_number = duplicate.card_number;
+            }
+            primary.claims_returned_count += duplicate.claims_returned_count;
+        }
+
+        let borrowed: i64 = loans::table
+            .filter(loans::member_id.eq(primary_id))
+            .filter(loans::status.eq_any([LoanStatus::Open, LoanStatus::Overdue]))
+            .count()
+            .get_result(conn)?;
+        diesel::update(members::table.filter(members::member_id.eq(primary_id)))
+            .set((
+                members::borrowed.eq(borrowed as i32),
+                members::email.eq(&primary.email),
+                members::external_id.eq(&primary.external_id),
+                members::card_number.eq(&primary.card_number),
+                members::claims_returned_count.eq(primary.claims_returned_count),
+            ))
+            .execute(conn)?;
+
+        Ok(MergeReport {
+            primary_id,
+            merged: request.duplicate_ids.clone(),
+            loans_moved,
+            borrowed: borrowed as i32,
+        })
+    })
+}
+
+/// Moves the holds of `from` over to `to`. Where both queue for the same book
+/// the hold of `from` is cancelled, keeping the place of `to` in the queue.
+fn move_holds(from: Uuid, to: Uuid, conn: &mut PgConnection) -> Result<()> {
+    let queued: Vec<Uuid> = holds::table
+        .filter(holds::member_id.eq(to))
+        .filter(holds::status.eq_any(ACTIVE))
+        .select(holds::book_id)
+        .load(conn)?;
+    let clashing: Vec<Uuid> = holds::table
+        .filter(holds::member_id.eq(from))
+        .filter(holds::status.eq_any(ACTIVE))
+        .filter(holds::book_id.eq_any(&queued))
+        .select(holds::hold_id)
+        .load(conn)?;
+    for hold_id in clashing {
+        cancel_hold(hold_id, conn)?;
+    }
+    diesel::update(holds::table.filter(holds::member_id.eq(from)))
+        .set(holds::member_id.eq(to))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Re-points guardian and dependent links from `from` to `to`, dropping links
+/// that would tie the member to itself or that already exist.
+fn move_relationships(from: Uuid, to: Uuid, conn: &mut PgConnection) -> Result<()> {
+    let links: Vec<(Uuid, Uuid)> = member_relationships::table
+        .filter(
+            member_relationships::guardian_id
+                .eq(from)
+                .or(member_relationships::dependent_id.eq(from)),
+        )
+        .select((
+            member_relationships::guardian_id,
+            member_relationships::dependent_id,
+        ))
+        .load(conn)?;
+    diesel::delete(
+        member_relationships::table.filter(
+            member_relationships::guardian_id
+                .eq(from)
+                .or(member_relationships::dependent_id.eq(from)),
+        ),
+    )
+    .execute(conn)?;
+
+    let swap = |id: Uuid| if id == from { to } else { id };
+    for (guardian_id, dependent_id) in links {
+        let (guardian_id, dependent_id) = (swap(guardian_id), swap(dependent_id));
+        if guardian_id == dependent_id {
+            continue;
+        }
+        diesel::insert_into(member_relationships::table)
+            .values((
+                member_relationships::guardian_id.eq(guardian_id),
+                member_relationships::dependent_id.eq(dependent_id),
+            ))
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+pub fn get_merges(primary_id: Uuid, conn: &mut PgConnection) -> Result<Vec<MemberMerge>> {
+    Ok(member_merges::table
+        .filter(member_merges::primary_id.eq(primary_id))
+        .order(member_merges::merged_at.desc())
+        .select(MemberMerge::as_select())
+        .load(conn)?)
+}
@@ -1,22 +1,120 @@
+use std::io::Write;
+
+use actix_web::error::ErrorBadRequest;
 use anyhow::Result;
+use chrono::{NaiveDate, NaiveDateTime};
 use diesel::{
+    deserialize::FromSql,
+    pg::{Pg, PgValue},
     prelude::{Insertable, Queryable},
-    AsChangeset, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
-    Selectable,
+    serialize::{IsNull, ToSql},
+    AsChangeset, AsExpression, BoolExpressionMethods, Connection, ExpressionMethods, FromSqlRow,
+    OptionalExtension, PgConnection, QueryDsl, RunQueryDsl, Selectable,
 };
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::schema::members;
+use crate::{
+    api_keys::models::{get_issued_api_keys, revoke_issued_api_keys, ApiKey},
+    auth::models::{delete_credentials, get_member_sessions, get_username, Session},
+    blocks::models::{get_blocks, MemberBlock},
+    errors::LibError,
+    fines::models::{get_account, get_balance, Account},
+    holds::models::{cancel_member_holds, get_member_holds, MemberHold},
+    households::models::{get_dependents, get_guardians},
+    ill::models::{get_member_requests, IllRequest},
+    loans::models::{detach_returned_loans, get_loan_history, LoanStatus, MemberLoan},
+    notes::models::{get_notes, MemberNote},
+    notices::models::{get_member_notices, Notice},
+    schema::{
+        fines, holds, ill_requests, loans, member_blocks, member_merges, member_notes,
+        member_relationships, members, notices,
+    },
+};
+
+use super::merge::{get_merges, MemberMerge};
+
+const ANONYMIZED_NAME: &str = "Anonymized member";
 
 #[derive(Debug, Insertable, Deserialize)]
 #[diesel(table_name = members)]
 pub struct NewMember {
     pub name: String,
-    pub email: String,
+    pub email: Option<String>,
     pub privilege: bool,
     pub borrowed: i32,
+    #[serde(default)]
+    pub household_limit: Option<i32>,
+    #[serde(default)]
+    pub external_id: Option<String>,
+    #[serde(default = "default_tier")]
+    pub tier: String,
+    #[serde(default)]
+    pub expires_on: Option<NaiveDate>,
+    #[serde(default)]
+    pub card_number: Option<String>,
+    #[serde(default)]
+    pub dependent: bool,
+}
+
+pub const DEFAULT_TIER: &str = "standard";
+
+/// The tiers members may be put in, unless `MEMBER_TIERS` lists others.
+pub const DEFAULT_MEMBER_TIERS: &str = "standard,student,faculty,staff";
+
+pub fn is_known_tier(tier: &str) -> bool {
+    std::env::var("MEMBER_TIERS")
+        .unwrap_or_else(|_| DEFAULT_MEMBER_TIERS.to_string())
+        .split(',')
+        .any(|known| known.trim() == tier)
+}
+
+fn default_tier() -> String {
+    DEFAULT_TIER.to_string()
+}
+
+/// What a member may do, see [`crate::auth::permissions`].
+#[derive(Debug, PartialEq, FromSqlRow, AsExpression, Eq, Serialize, Deserialize, Clone, Copy)]
+#[diesel(sql_type = crate::schema::sql_types::MemberRole)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Patron,
+    Circulation,
+    Cataloger,
+    Admin,
+}
+
+impl ToSql<crate::schema::sql_types::MemberRole, Pg> for Role {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, Pg>,
+    ) -> diesel::serialize::Result {
+        match *self {
+            Role::Patron => out.write_all(b"patron")?,
+            Role::Circulation => out.write_all(b"circulation")?,
+            Role::Cataloger => out.write_all(b"cataloger")?,
+            Role::Admin => out.write_all(b"admin")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<crate::schema::sql_types::MemberRole, Pg> for Role {
+    fn from_sql(bytes: PgValue<'_>) -> diesel::deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"patron" => Ok(Role::Patron),
+            b"circulation" => Ok(Role::Circulation),
+            b"cataloger" => Ok(Role::Cataloger),
+            b"admin" => Ok(Role::Admin),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoleRequest {
+    pub role: Role,
 }
 
 #[derive(Debug, Queryable, Selectable, AsChangeset, Serialize, Deserialize)]
@@ -25,22 +123,70 @@ pub struct NewMember {
 pub struct Member {
     pub member_id: Uuid,
     pub name: String,
-    pub email: String,
+    pub email: Option<String>,
     pub borrowed: i32,
     pub privilege: bool,
+    pub household_limit: Option<i32>,
+    pub anonymized_at: Option<NaiveDateTime>,
+    pub role: Role,
+    pub external_id: Option<String>,
+    pub tier: String,
+    pub expires_on: Option<NaiveDate>,
+    pub keep_history: bool,
+    pub card_number: Option<String>,
+    /// Loans the member said they returned that were never checked in.
+    pub claims_returned_count: i32,
+    /// Dependents are reached through a guardian and may have no email.
+    pub dependent: bool,
 }
 
-pub fn add_member(
-    name: &str,
-    email: &str,
-    privilege: bool,
-    conn: &mut PgConnection,
-) -> Result<uuid::Uuid> {
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PrivacySettings {
+    pub keep_history: bool,
+}
+
+/// Everything the library holds about a member, as returned for a subject
+/// access request.
+#[derive(Debug, Serialize)]
+pub struct MemberExport {
+    pub exported_at: NaiveDateTime,
+    pub member: Member,
+    pub guardians: Vec<Uuid>,
+    pub dependents: Vec<Uuid>,
+    pub loans: Vec<MemberLoan>,
+    pub privacy: PrivacySettings,
+    pub username: Option<String>,
+    pub sessions: Vec<Session>,
+    pub fines: Account,
+    pub holds: Vec<MemberHold>,
+    pub blocks: Vec<MemberBlock>,
+    pub notes: Vec<MemberNote>,
+    /// With the address every notice went to.
+    pub notices: Vec<Notice>,
+    pub ill_requests: Vec<IllRequest>,
+    /// Members merged into this one, with what they held at the time.
+    pub merges: Vec<MemberMerge>,
+    /// Keys issued by the member, for staff.
+    pub api_keys: Vec<ApiKey>,
+}
+
+/// Patrons get their notices by email, so only dependents and staff accounts
+/// may go without an address.
+fn require_email(email: Option<&str>, dependent: bool, role: Role) -> Result<()> {
+    if email.is_none() && !dependent && role == Role::Patron {
+        return Err(LibError::ActixError(
+            ErrorBadRequest("only dependents may be without an email").to_string(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub fn add_member(member: NewMember, conn: &mut PgConnection) -> Result<uuid::Uuid> {
+    require_email(member.email.as_deref(), member.dependent, Role::Patron)?;
     let member = NewMember {
-        name: name.to_string(),
-        email: email.to_string(),
         borrowed: 0,
-        privilege,
+        ..member
     };
 
     let member_id = diesel::insert_into(members::table)
@@ -58,25 +204,214 @@ pub fn get_member(id: uuid::Uuid, conn: &mut PgConnection) -> Result<Option<Memb
         .optional()?)
 }
 
+pub fn get_member_by_card(card_number: &str, conn: &mut PgConnection) -> Result<Option<Member>> {
+    Ok(members::table
+        .filter(members::card_number.eq(card_number.trim()))
+        .first(conn)
+        .optional()?)
+}
+
 pub fn update_member(id: Uuid, payload: NewMember, conn: &mut PgConnection) -> Result<bool> {
-    use crate::schema::members::dsl::{borrowed, email, member_id, members, name};
+    use crate::schema::members::dsl::{
+        borrowed, card_number, dependent, email, expires_on, external_id, household_limit,
+        member_id, members, name, tier,
+    };
+
+    let Some(member) = get_member(id, conn)? else {
+        return Ok(false);
+    };
+    require_email(payload.email.as_deref(), payload.dependent, member.role)?;
 
     let num_updated = diesel::update(members.filter(member_id.eq(id)))
         .set((
             name.eq(payload.name),
             email.eq(payload.email),
             borrowed.eq(payload.borrowed),
+            household_limit.eq(payload.household_limit),
+            external_id.eq(payload.external_id),
+            tier.eq(payload.tier),
+            expires_on.eq(payload.expires_on),
+            card_number.eq(payload.card_number),
+            dependent.eq(payload.dependent),
         ))
         .execute(conn)?;
 
     Ok(num_updated > 0)
 }
 
+/// Turns reading history on or off for a member. Turning it off also detaches
+/// the loans the member already returned, see [`detach_returned_loans`].
+pub fn update_privacy(
+    id: Uuid,
+    settings: &PrivacySettings,
+    conn: &mut PgConnection,
+) -> Result<bool> {
+    conn.transaction(|conn| {
+        let num_updated = diesel::update(members::table.filter(members::member_id.eq(id)))
+            .set(members::keep_history.eq(settings.keep_history))
+            .execute(conn)?;
+        if num_updated > 0 && !settings.keep_history {
+            detach_returned_loans(id, conn)?;
+        }
+        Ok(num_updated > 0)
+    })
+}
+
+pub fn update_member_role(id: Uuid, role: Role, conn: &mut PgConnection) -> Result<bool> {
+    let num_updated = diesel::update(members::table.filter(members::member_id.eq(id)))
+        .set(members::role.eq(role))
+        .execute(conn)?;
+    Ok(num_updated > 0)
+}
+
+/// Deletes a member that never borrowed anything.
+///
+/// Members with loan history are kept so the loans stay consistent; use
+/// [`anonymize_member`] to erase their personal data instead.
 pub fn delete_member(id: uuid::Uuid, conn: &mut PgConnection) -> Result<()> {
-    let num_deleted: usize =
-        diesel::delete(members::dsl::members.filter(members::member_id.eq(id))).execute(conn)?;
-    if num_deleted == 0 {
-        return Err(anyhow::anyhow!("Could not delete book."));
-    }
-    Ok(())
+    conn.transaction(|conn| {
+        let num_loans: i64 = loans::table
+            .filter(loans::member_id.eq(id))
+            .count()
+            .get_result(conn)?;
+        if num_loans > 0 {
+            return Err(LibError::Conflict(format!(
+                "member {id} has loan history and cannot be deleted, anonymize it instead"
+            ))
+            .into());
+        }
+
+        diesel::delete(
+            member_relationships::table.filter(
+                member_relationships::guardian_id
+                    .eq(id)
+                    .or(member_relationships::dependent_id.eq(id)),
+            ),
+        )
+        .execute(conn)?;
+        delete_credentials(id, conn)?;
+        revoke_issued_api_keys(id, conn)?;
+        cancel_member_holds(id, conn)?;
+        diesel::delete(holds::table.filter(holds::member_id.eq(id))).execute(conn)?;
+        diesel::delete(fines::table.filter(fines::member_id.eq(id))).execute(conn)?;
+        diesel::delete(notices::table.filter(notices::member_id.eq(id))).execute(conn)?;
+        diesel::delete(ill_requests::table.filter(ill_requests::member_id.eq(id))).execute(conn)?;
+        diesel::delete(member_blocks::table.filter(member_blocks::member_id.eq(id)))
+            .execute(conn)?;
+        diesel::delete(member_notes::table.filter(member_notes::member_id.eq(id))).execute(conn)?;
+        diesel::delete(member_merges::table.filter(member_merges::primary_id.eq(id)))
+            .execute(conn)?;
+        let num_deleted: usize =
+            diesel::delete(members::dsl::members.filter(members::member_id.eq(id)))
+                .execute(conn)?;
+        if num_deleted == 0 {
+            return Err(anyhow::anyhow!("Could not delete member."));
+        }
+        Ok(())
+    })
+}
+
+pub async fn export_member(id: Uuid, conn: &mut PgConnection) -> Result<Option<MemberExport>> {
+    let Some(member) = get_member(id, conn)? else {
+        return Ok(None);
+    };
+
+    Ok(Some(MemberExport {
+        exported_at: chrono::Utc::now().naive_utc(),
+        guardians: get_guardians(id, conn)?
+            .into_iter()
+            .map(|m| m.member_id)
+            .collect(),
+        dependents: get_dependents(id, conn)?
+            .into_iter()
+            .map(|m| m.member_id)
+            .collect(),
+        loans: get_loan_history(id, conn).await?,
+        privacy: PrivacySettings {
+            keep_history: member.keep_history,
+        },
+        username: get_username(id, conn)?,
+        sessions: get_member_sessions(id, conn)?,
+        fines: get_account(id, conn)?,
+        holds: get_member_holds(id, None, conn)?,
+        blocks: get_blocks(id, false, conn)?,
+        notes: get_notes(id, conn)?,
+        notices: get_member_notices(id, conn)?,
+        ill_requests: get_member_requests(id, conn)?,
+        merges: get_merges(id, conn)?,
+        api_keys: get_issued_api_keys(id, conn)?,
+        member,
+    }))
+}
+
+/// Scrubs the personal data of a member while keeping the member row, and so
+/// their loans, around for statistics. Refuses while the member still has
+/// books out, claims a book was returned, or lost a book and owes money.
+pub fn anonymize_member(id: Uuid, conn: &mut PgConnection) -> Result<bool> {
+    conn.transaction(|conn| {
+        // claims returned are unresolved until the book turns up or is written
+        // off, so they block like loans that are still out
+        let num_open: i64 = loans::table
+            .filter(loans::member_id.eq(id))
+            .filter(loans::status.eq_any([
+                LoanStatus::Open,
+                LoanStatus::Overdue,
+                LoanStatus::ClaimsReturned,
+            ]))
+            .count()
+            .get_result(conn)?;
+        if num_open > 0 {
+            return Err(
+                LibError::Conflict(format!("member {id} still has {num_open} open loans")).into(),
+            );
+        }
+        // a lost book blocks as long as the member owes money, since payments
+        // are not booked against a loan and the replacement may be unpaid
+        let num_lost: i64 = loans::table
+            .filter(loans::member_id.eq(id))
+            .filter(loans::status.eq(LoanStatus::Lost))
+            .count()
+            .get_result(conn)?;
+        if num_lost > 0 && get_balance(id, conn)? > 0 {
+            return Err(LibError::Conflict(format!(
+                "member {id} has {num_lost} lost books and an unpaid balance"
+            ))
+            .into());
+        }
+
+        diesel::delete(
+            member_relationships::table.filter(
+                member_relationships::guardian_id
+                    .eq(id)
+                    .or(member_relationships::dependent_id.eq(id)),
+            ),
+        )
+        .execute(conn)?;
+        delete_credentials(id, conn)?;
+        // keys outlive whoever issued them otherwise
+        revoke_issued_api_keys(id, conn)?;
+        cancel_member_holds(id, conn)?;
+        diesel::delete(member_blocks::table.filter(member_blocks::member_id.eq(id)))
+            .execute(conn)?;
+        diesel::delete(member_notes::table.filter(member_notes::member_id.eq(id))).execute(conn)?;
+        diesel::delete(member_merges::table.filter(member_merges::primary_id.eq(id)))
+            .execute(conn)?;
+
+        // the notice log keeps its rows for statistics, without the address
+        diesel::update(notices::table.filter(notices::member_id.eq(id)))
+            .set(notices::recipient.eq(""))
+            .execute(conn)?;
+
+        let num_updated = diesel::update(members::table.filter(members::member_id.eq(id)))
+            .set((
+                members::name.eq(ANONYMIZED_NAME),
+                members::email.eq(None::<String>),
+                members::household_limit.eq(None::<i32>),
+                members::card_number.eq(None::<String>),
+                members::external_id.eq(None::<String>),
+                members::anonymized_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(conn)?;
+        Ok(num_updated > 0)
+    })
 }
@@ -0,0 +1,63 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+    members::models::get_member,
+};
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+
+use super::models::{add_note as create_note, delete_note, get_notes, NoteRequest};
+
+#[get("/members/{member_id}/notes")]
+async fn fetch_notes(principal: Principal, member_id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageNotes) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_notes(*member_id, &mut conn) {
+        Ok(notes) => HttpResponse::Ok().json(notes),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[post("/members/{member_id}/notes")]
+async fn add_note(
+    principal: Principal,
+    member_id: web::Path<uuid::Uuid>,
+    payload: web::Json<NoteRequest>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageNotes) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_member(*member_id, &mut conn) {
+        Ok(Some(_)) => {}
+        Ok(None) => return HttpResponse::NotFound().json(format!("member {member_id} not found")),
+        Err(e) => return HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+    match create_note(
+        *member_id,
+        payload.into_inner().body,
+        principal.member_id,
+        &mut conn,
+    ) {
+        Ok(note_id) => HttpResponse::Ok().json(note_id),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[delete("/members/{member_id}/notes/{note_id}")]
+async fn remove_note(
+    principal: Principal,
+    path: web::Path<(uuid::Uuid, uuid::Uuid)>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManageNotes) {
+        return denied;
+    }
+    let (member_id, note_id) = path.into_inner();
+    let mut conn = establish_connection();
+    match delete_note(member_id, note_id, &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::{Insertable, Queryable},
+    ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl, Selectable, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::schema::member_notes;
+
+#[derive(Debug, Deserialize)]
+pub struct NoteRequest {
+    pub body: String,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = member_notes)]
+pub struct NewNote {
+    pub member_id: Uuid,
+    pub body: String,
+    pub created_by: Option<Uuid>,
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize, Deserialize)]
+#[diesel(table_name = member_notes)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct MemberNote {
+    pub note_id: Uuid,
+    pub member_id: Uuid,
+    pub body: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: NaiveDateTime,
+}
+
+pub fn add_note(
+    member_id: Uuid,
+    body: String,
+    created_by: Option<Uuid>,
+    conn: &mut PgConnection,
+) -> Result<Uuid> {
+    Ok(diesel::insert_into(member_notes::table)
+        .values(&NewNote {
+            member_id,
+            body,
+            created_by,
+        })
+        .returning(member_notes::note_id)
+        .get_result(conn)?)
+}
+
+pub fn get_notes(member_id: Uuid, conn: &mut PgConnection) -> Result<Vec<MemberNote>> {
+    Ok(member_notes::table
+        .filter(member_notes::member_id.eq(member_id))
+        .order(member_notes::created_at.desc())
+        .select(MemberNote::as_select())
+        .load(conn)?)
+}
+
+pub fn delete_note(member_id: Uuid, note_id: Uuid, conn: &mut PgConnection) -> Result<bool> {
+    let num_deleted = diesel::delete(
+        member_notes::table
+            .filter(member_notes::note_id.eq(note_id))
+            .filter(member_notes::member_id.eq(member_id)),
+    )
+    .execute(conn)?;
+    Ok(num_deleted > 0)
+}
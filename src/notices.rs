@@ -0,0 +1,4 @@
+pub mod handlers;
+pub mod models;
+pub mod templates;
+pub mod transport;
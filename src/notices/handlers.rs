@@ -0,0 +1,64 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+};
+use actix_web::{get, post, web, HttpResponse, Responder};
+
+use super::models::{get_member_notices, send_notices, NoticeConfig};
+
+/// Sends the notices that are due right away instead of waiting for the
+/// overdue sweeper.
+#[post("/admin/notices/send")]
+async fn send(principal: Principal, config: web::Data<NoticeConfig>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::RunMaintenance) {
+        return denied;
+    }
+    let Some(transport) = config.transport.clone() else {
+        return HttpResponse::ServiceUnavailable().json("no notice transport is configured");
+    };
+    let due_soon_days = config.due_soon_days;
+    let today = chrono::Utc::now().date_naive();
+    let sent = web::block(move || {
+        send_notices(
+            today,
+            due_soon_days,
+            transport.as_ref(),
+            &mut establish_connection(),
+        )
+    })
+    .await;
+    match sent {
+        Ok(Ok(report)) => HttpResponse::Ok().json(report),
+        Ok(Err(e)) => {
+            HttpResponse::InternalServerError().json(format!("failed to send notices {e}"))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+/// Notices kept by the transport, which is only the in-memory one.
+#[get("/admin/notices/outbox")]
+async fn outbox(principal: Principal, config: web::Data<NoticeConfig>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::RunMaintenance) {
+        return denied;
+    }
+    match &config.transport {
+        Some(transport) => HttpResponse::Ok().json(transport.outbox()),
+        None => HttpResponse::ServiceUnavailable().json("no notice transport is configured"),
+    }
+}
+
+#[get("/members/{member_id}/notices")]
+async fn fetch_member_notices(
+    principal: Principal,
+    member_id: web::Path<uuid::Uuid>,
+) -> impl Responder {
+    if let Err(denied) = principal.require_for(*member_id, Permission::ReadMembers) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_member_notices(*member_id, &mut conn) {
+        Ok(notices) => HttpResponse::Ok().json(notices),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use diesel::{
+    dsl::{exists, not},
+    prelude::Queryable,
+    ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl, Selectable,
+    SelectableHelper,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    loans::models::LoanStatus,
+    schema::{books, loans, members, notices},
+};
+
+use super::{
+    templates::{render, Template, DUE_SOON, OVERDUE, RECALL},
+    transport::{transport_from_env, Message, NoticeTransport},
+};
+
+pub const DEFAULT_DUE_SOON_DAYS: i64 = 3;
+
+/// Where notices go and how far ahead of the due date members are reminded.
+#[derive(Clone)]
+pub struct NoticeConfig {
+    pub due_soon_days: i64,
+    pub transport: Option<Arc<dyn NoticeTransport>>,
+}
+
+impl NoticeConfig {
+    /// Reads `NOTICE_DUE_SOON_DAYS` and the transport settings, see
+    /// [`transport_from_env`].
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            due_soon_days: std::env::var("NOTICE_DUE_SOON_DAYS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_DUE_SOON_DAYS),
+            transport: transport_from_env()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoticeKind {
+    DueSoon,
+    Overdue,
+    /// The due date of the loan was moved up because someone recalled it.
+    Recall,
+}
+
+impl NoticeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NoticeKind::DueSoon => "due_soon",
+            NoticeKind::Overdue => "overdue",
+            NoticeKind::Recall => "recall",
+        }
+    }
+
+    fn template(self) -> &'static Template {
+        match self {
+            NoticeKind::DueSoon => &DUE_SOON,
+            NoticeKind::Overdue => &OVERDUE,
+            NoticeKind::Recall => &RECALL,
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = notices)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Notice {
+    pub notice_id: Uuid,
+    pub loan_id: Uuid,
+    pub member_id: Uuid,
+    pub kind: String,
+    pub due_date: NaiveDate,
+    pub recipient: String,
+    pub sent: bool,
+    pub error: Option<String>,
+    pub attempted_at: NaiveDateTime,
+}
+
+/// A loan that is owed a notice.
+#[derive(Debug, Queryable)]
+struct PendingNotice {
+    loan_id: Uuid,
+    due_date: NaiveDate,
+    member_id: Uuid,
+    name: String,
+    email: Option<String>,
+    title: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct NoticeReport {
+    pub due_soon: usize,
+    pub overdue: usize,
+    pub recall: usize,
+    pub failed: usize,
+}
+
+/// Loans of members with an email address that are due within
+/// `due_soon_days`, already overdue or recalled, and were not noticed for their
+/// current due date yet.
+fn find_pending(
+    kind: NoticeKind,
+    today: NaiveDate,
+    due_soon_days: i64,
+    conn: &mut PgConnection,
+) -> Result<Vec<PendingNotice>> {
+    let query = loans::table
+        .inner_join(members::table)
+        .inner_join(books::table)
+        .filter(loans::status.eq_any([LoanStatus::Open, LoanStatus::Overdue]))
+        .filter(members::email.is_not_null())
+        .filter(not(exists(
+            notices::table
+                .filter(notices::loan_id.eq(loans::loan_id))
+                .filter(notices::kind.eq(kind.as_str()))
+                .filter(notices::due_date.eq(loans::due_date))
+                .filter(notices::sent),
+        )))
+        .select((
+            loans::loan_id,
+            loans::due_date,
+            members::member_id,
+            members::name,
+            members::email,
+            books::title,
+        ))
+        .into_boxed();
+
+    let query = match kind {
+        NoticeKind::DueSoon => query
+            .filter(loans::due_date.ge(today))
+            .filter(loans::due_date.le(today + Duration::days(due_soon_days))),
+        NoticeKind::Overdue => query.filter(loans::due_date.lt(today)),
+        NoticeKind::Recall => query.filter(loans::recalled_at.is_not_null()),
+    };
+    Ok(query.load(conn)?)
+}
+
+/// Sends every notice that is due as of `today` and records each attempt.
+///
+/// A notice is recorded as sent before it goes out, so two runs at the same
+/// time cannot both deliver it; a failed delivery is recorded as such and
+/// retried on the next run.
+pub fn send_notices(
+    today: NaiveDate,
+    due_soon_days: i64,
+    transport: &dyn NoticeTransport,
+    conn: &mut PgConnection,
+) -> Result<NoticeReport> {
+    let mut report = NoticeReport::default();
+    for kind in [NoticeKind::Recall, NoticeKind::Overdue, NoticeKind::DueSoon] {
+        for pending in find_pending(kind, today, due_soon_days, conn)? {
+            match deliver(kind, pending, today, transport, conn)? {
+                Some(true) => match kind {
+                    NoticeKind::DueSoon => report.due_soon += 1,
+                    NoticeKind::Overdue => report.overdue += 1,
+                    NoticeKind::Recall => report.recall += 1,
+                },
+                Some(false) => report.failed += 1,
+                None => {}
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Sends the recall notice of one loan right away instead of on the next run.
+/// Returns whether it went out.
+pub fn send_recall_notice(
+    loan_id: Uuid,
+    today: NaiveDate,
+    transport: &dyn NoticeTransport,
+    conn: &mut PgConnection,
+) -> Result<bool> {
+    let pending = find_pending(NoticeKind::Recall, today, 0, conn)?
+        .into_iter()
+        .find(|pending| pending.loan_id == loan_id);
+    match pending {
+        Some(pending) => {
+            Ok(deliver(NoticeKind::Recall, pending, today, transport, conn)?.unwrap_or(false))
+        }
+        None => Ok(false),
+    }
+}
+
+/// Reserves and sends one notice. Returns whether it went out, or `None` when
+/// there is nobody to send it to or it was sent already.
+fn deliver(
+    kind: NoticeKind,
+    pending: PendingNotice,
+    today: NaiveDate,
+    transport: &dyn NoticeTransport,
+    conn: &mut PgConnection,
+) -> Result<Option<bool>> {
+    let Some(recipient) = pending.email else {
+        return Ok(None);
+    };
+    let reserved: Option<Uuid> = diesel::insert_into(notices::table)
+        .values((
+            notices::loan_id.eq(pending.loan_id),
+            notices::member_id.eq(pending.member_id),
+            notices::kind.eq(kind.as_str()),
+            notices::due_date.eq(pending.due_date),
+            notices::recipient.eq(&recipient),
+            notices::sent.eq(true),
+        ))
+        .on_conflict_do_nothing()
+        .returning(notices::notice_id)
+        .get_result(conn)
+        .optional()?;
+    let Some(notice_id) = reserved else {
+        return Ok(None);
+    };
+
+    let template = kind.template();
+    let values = [
+        ("name", pending.name),
+        ("title", pending.title),
+        ("due_date", pending.due_date.to_string()),
+        (
+            "days",
+            (pending.due_date - today).num_days().abs().to_string(),
+        ),
+    ];
+    let message = Message {
+        to: recipient,
+        subject: render(template.subject, &values),
+        body: render(template.body, &values),
+    };
+
+    match transport.send(&message) {
+        Ok(()) => Ok(Some(true)),
+        Err(e) => {
+            diesel::update(notices::table.find(notice_id))
+                .set((notices::sent.eq(false), notices::error.eq(e.to_string())))
+                .execute(conn)?;
+            Ok(Some(false))
+        }
+    }
+}
+
+/// Every notice attempt for a member, newest first.
+pub fn get_member_notices(member_id: Uuid, conn: &mut PgConnection) -> Result<Vec<Notice>> {
+    Ok(notices::table
+        .filter(notices::member_id.eq(member_id))
+        .order(notices::attempted_at.desc())
+        .select(Notice::as_select())
+        .load(conn)?)
+}
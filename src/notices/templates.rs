@@ -0,0 +1,101 @@
+/// The subject and body of a notice, with `{{name}}`, `{{title}}`,
+/// `{{due_date}}` and `{{days}}` filled in per loan.
+pub struct Template {
+    pub subject: &'static str,
+    pub body: &'static str,
+}
+
+pub const DUE_SOON: Template = Template {
+    subject: "\"{{title}}\" is due on {{due_date}}",
+    body: "Hello {{name}},
+
+\"{{title}}\" is due back in {{days}} day(s), on {{due_date}}. You can renew it
+unless someone else is waiting for it.
+",
+};
+
+pub const OVERDUE: Template = Template {
+    subject: "\"{{title}}\" is overdue",
+    body: "Hello {{name}},
+
+\"{{title}}\" was due back on {{due_date}}, {{days}} day(s) ago. Please return it
+as soon as possible; late fees may apply.
+",
+};
+
+pub const RECALL: Template = Template {
+    subject: "\"{{title}}\" was recalled",
+    body: "Hello {{name}},
+
+Another reader needs \"{{title}}\", so it is now due back on {{due_date}}, in
+{{days}} day(s). It cannot be renewed, and late fees are higher for recalled
+books.
+",
+};
+
+/// Replaces every `{{key}}` in `template` with its value.
+pub fn render(template: &str, values: &[(&str, String)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |text, (key, value)| {
+            text.replace(&format!("{{{{{key}}}}}"), value)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> Vec<(&'static str, String)> {
+        vec![
+            ("name", "Ann".to_string()),
+            ("title", "Dune".to_string()),
+            ("due_date", "2023-06-15".to_string()),
+            ("days", "3".to_string()),
+        ]
+    }
+
+    #[test]
+    fn every_placeholder_is_filled_in() {
+        for template in [DUE_SOON, OVERDUE, RECALL] {
+            let subject = render(template.subject, &values());
+            let body = render(template.body, &values());
+            assert!(!subject.contains("{{"), "{subject}");
+            assert!(!body.contains("{{"), "{body}");
+            assert!(body.starts_with("Hello Ann,\n"), "{body}");
+        }
+    }
+
+    #[test]
+    fn notices_read_as_written() {
+        assert_eq!(
+            render(DUE_SOON.subject, &values()),
+            "\"Dune\" is due on 2023-06-15"
+        );
+        assert_eq!(
+            render(OVERDUE.body, &values()),
+            "Hello Ann,
+
+\"Dune\" was due back on 2023-06-15, 3 day(s) ago. Please return it
+as soon as possible; late fees may apply.
+"
+        );
+    }
+
+    #[test]
+    fn placeholders_are_replaced_wherever_they_repeat() {
+        assert_eq!(
+            render(
+                "{{a}}-{{b}}-{{a}}",
+                &[("a", "1".to_string()), ("b", "2".to_string())]
+            ),
+            "1-2-1"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_left_alone() {
+        assert_eq!(render("{{name}} {{nope}}", &values()), "Ann {{nope}}");
+        assert_eq!(render("{name}", &values()), "{name}");
+    }
+}
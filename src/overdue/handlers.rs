@@ -0,0 +1,28 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+};
+use actix_web::{post, HttpResponse, Responder};
+
+use super::models::sweep;
+
+/// Runs the overdue sweep right away instead of waiting for the next tick.
+#[post("/admin/overdue/sweep")]
+async fn run_sweep(principal: Principal) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::RunMaintenance) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match sweep(chrono::Utc::now().date_naive(), &mut conn) {
+        Ok(report) => {
+            log::info!(
+                "overdue sweep by {}: {} loans marked overdue, {} fines accrued",
+                principal.subject,
+                report.marked_overdue,
+                report.fines_accrued
+            );
+            HttpResponse::Ok().json(report)
+        }
+        Err(e) => HttpResponse::InternalServerError().json(format!("overdue sweep failed {e}")),
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use diesel::{
+    Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl, SelectableHelper,
+};
+use serde::Serialize;
+
+use crate::{
+    db::establish_connection,
+    fines::models::assess_fine,
+    holds::models::expire_holds,
+    loans::models::{Loan, LoanStatus},
+    members::models::Member,
+    notices::models::{send_notices, NoticeConfig},
+    schema::{loans, members},
+};
+
+pub const DEFAULT_SWEEP_INTERVAL_MINUTES: u64 = 60;
+
+/// How often the overdue sweeper runs, from `OVERDUE_SWEEP_MINUTES`. Zero turns
+/// the background task off, leaving only the admin endpoint.
+#[derive(Clone, Copy)]
+pub struct SweeperConfig {
+    pub interval: Option<std::time::Duration>,
+}
+
+impl SweeperConfig {
+    pub fn from_env() -> Self {
+        let minutes = std::env::var("OVERDUE_SWEEP_MINUTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_SWEEP_INTERVAL_MINUTES);
+        Self {
+            interval: (minutes > 0).then(|| std::time::Duration::from_secs(minutes * 60)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SweepReport {
+    pub swept_on: NaiveDate,
+    /// Open loans that were past their due date.
+    pub marked_overdue: usize,
+    /// Overdue loans whose late fee went up.
+    pub fines_accrued: usize,
+}
+
+/// Marks open loans past their due date as overdue and brings the late fees of
+/// every overdue loan up to `today`.
+///
+/// Running it twice on the same day changes nothing the second time.
+pub fn sweep(today: NaiveDate, conn: &mut PgConnection) -> Result<SweepReport> {
+    conn.transaction(|conn| {
+        let marked_overdue = diesel::update(
+            loans::table
+                .filter(loans::status.eq(LoanStatus::Open))
+                .filter(loans::due_date.lt(today)),
+        )
+        .set(loans::status.eq(LoanStatus::Overdue))
+        .execute(conn)?;
+
+        let overdue: Vec<(Loan, Member)> = loans::table
+            .inner_join(members::table)
+            .filter(loans::status.eq(LoanStatus::Overdue))
+            .select((Loan::as_select(), Member::as_select()))
+            .load(conn)?;
+        let mut fines_accrued = 0;
+        for (loan, member) in &overdue {
+            if assess_fine(loan, member, today, conn)? > 0 {
+                fines_accrued += 1;
+            }
+        }
+
+        Ok(SweepReport {
+            swept_on: today,
+            marked_overdue,
+            fines_accrued,
+        })
+    })
+}
+
+/// Sweeps once every `interval` for as long as the server runs, expiring the
+/// holds left on the hold shelf and sending the notices that are due
+/// afterwards. Failures are logged and retried on the next tick.
+pub async fn run_sweeper(interval: std::time::Duration, notices: NoticeConfig) {
+    let mut ticks = actix_rt::time::interval(interval);
+    loop {
+        ticks.tick().await;
+        let today = chrono::Utc::now().date_naive();
+        let swept = actix_web::web::block(move || sweep(today, &mut establish_connection())).await;
+        match swept {
+            Ok(Ok(report)) => log::info!(
+                "overdue sweep: {} loans marked overdue, {} fines accrued",
+                report.marked_overdue,
+                report.fines_accrued
+            ),
+            Ok(Err(e)) => log::error!("overdue sweep failed: {e}"),
+            Err(e) => log::error!("overdue sweep could not run: {e}"),
+        }
+
+        let expired =
+            actix_web::web::block(move || expire_holds(today, &mut establish_connection())).await;
+        match expired {
+            Ok(Ok(holds)) => log::info!("hold expiry: {} holds expired", holds.len()),
+            Ok(Err(e)) => log::error!("hold expiry failed: {e}"),
+            Err(e) => log::error!("hold expiry could not run: {e}"),
+        }
+
+        let Some(transport) = notices.transport.clone() else {
+            continue;
+        };
+        let due_soon_days = notices.due_soon_days;
+        let sent = actix_web::web::block(move || {
+            send_notices(
+                today,
+                due_soon_days,
+                transport.as_ref(),
+                &mut establish_connection(),
+            )
+        })
+        .await;
+        match sent {
+            Ok(Ok(report)) => log::info!(
+                "notices: {} due soon, {} overdue, {} recalls, {} failed",
+                report.due_soon,
+                report.overdue,
+                report.recall,
+                report.failed
+            ),
+            Ok(Err(e)) => log::error!("sending notices failed: {e}"),
+            Err(e) => log::error!("sending notices could not run: {e}"),
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_PER_PAGE: i64 = 20;
+const MAX_PER_PAGE: i64 = 100;
+
+/// `?page=&per_page=` query parameters shared by list endpoints.
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+pub struct Pagination {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl Pagination {
+    pub fn page(&self) -> i64 {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    pub fn per_page(&self) -> i64 {
+        self.per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE)
+    }
+
+    /// Saturates rather than overflows for absurdly large pages, which then
+    /// simply come back empty.
+    pub fn offset(&self) -> i64 {
+        (self.page() - 1).saturating_mul(self.per_page())
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+    errors::error_response,
+};
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+
+use super::models::{
+    add_rule, delete_rule, get_rules, resolve_rule, update_rule, LoanContext, RuleRequest,
+};
+
+#[get("/admin/circulation-rules")]
+async fn fetch_rules(principal: Principal) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManagePolicies) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match get_rules(&mut conn) {
+        Ok(rules) => HttpResponse::Ok().json(rules),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+#[post("/admin/circulation-rules")]
+async fn create_rule(principal: Principal, payload: web::Json<RuleRequest>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManagePolicies) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match add_rule(payload.into_inner(), &mut conn) {
+        Ok(rule_id) => HttpResponse::Ok().json(rule_id),
+        Err(e) => HttpResponse::BadRequest().json(format!("failed to add rule {e}")),
+    }
+}
+
+#[put("/admin/circulation-rules/{rule_id}")]
+async fn change_rule(
+    principal: Principal,
+    rule_id: web::Path<uuid::Uuid>,
+    payload: web::Json<RuleRequest>,
+) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManagePolicies) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match update_rule(*rule_id, payload.into_inner(), &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::BadRequest().json(format!("failed to update rule {e}")),
+    }
+}
+
+#[delete("/admin/circulation-rules/{rule_id}")]
+async fn remove_rule(principal: Principal, rule_id: web::Path<uuid::Uuid>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ManagePolicies) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match delete_rule(*rule_id, &mut conn) {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
+
+/// Shows which rule a loan would fall under, e.g.
+/// `?tier=standard&item_type=dvd&branch=main`.
+#[get("/circulation-rules/resolve")]
+async fn resolve(principal: Principal, context: web::Query<LoanContext>) -> impl Responder {
+    if let Err(denied) = principal.require(Permission::ReadLoans) {
+        return denied;
+    }
+    let mut conn = establish_connection();
+    match resolve_rule(&context, &mut conn) {
+        Ok(Some(rule)) => HttpResponse::Ok().json(rule),
+        Ok(None) => HttpResponse::NotFound().json("no matching circulation rule"),
+        Err(e) => error_response(&e),
+    }
+}
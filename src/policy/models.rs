@@ -0,0 +1,385 @@
+use actix_web::error::ErrorBadRequest;
+use anyhow::Result;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use diesel::{
+    prelude::{Insertable, Queryable},
+    AsChangeset, BoolExpressionMethods, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+    Selectable, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    closures::models::{get_calendar, Calendar},
+    errors::LibError,
+    schema::circulation_rules,
+};
+
+/// How far ahead staff may push a due date by hand.
+pub const MAX_OVERRIDE_DAYS: i64 = 365;
+
+#[derive(Debug, Insertable, AsChangeset, Deserialize)]
+#[diesel(table_name = circulation_rules)]
+#[diesel(treat_none_as_null = true)]
+pub struct RuleRequest {
+    pub tier: Option<String>,
+    pub item_type: Option<String>,
+    pub branch: Option<String>,
+    pub loan_days: i32,
+    #[serde(default = "default_max_renewals")]
+    pub max_renewals: i32,
+    #[serde(default)]
+    pub renewal_overdue_days: i32,
+    #[serde(default = "default_daily_fine_cents")]
+    pub daily_fine_cents: i32,
+    #[serde(default)]
+    pub fine_grace_days: i32,
+    #[serde(default = "default_max_fine_cents")]
+    pub max_fine_cents: i32,
+    #[serde(default = "default_recall_daily_fine_cents")]
+    pub recall_daily_fine_cents: i32,
+}
+
+fn default_max_renewals() -> i32 {
+    2
+}
+
+fn default_daily_fine_cents() -> i32 {
+    25
+}
+
+fn default_max_fine_cents() -> i32 {
+    1000
+}
+
+fn default_recall_daily_fine_cents() -> i32 {
+    100
+}
+
+#[derive(Debug, Queryable, Selectable, Serialize)]
+#[diesel(table_name = circulation_rules)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct CirculationRule {
+    pub rule_id: Uuid,
+    pub tier: Option<String>,
+    pub item_type: Option<String>,
+    pub branch: Option<String>,
+    pub loan_days: i32,
+    pub created_at: NaiveDateTime,
+    pub max_renewals: i32,
+    pub renewal_overdue_days: i32,
+    pub daily_fine_cents: i32,
+    pub fine_grace_days: i32,
+    pub max_fine_cents: i32,
+    /// Replaces `daily_fine_cents` once the loan was recalled.
+    pub recall_daily_fine_cents: i32,
+}
+
+impl CirculationRule {
+    /// The late fee for a loan due on `due_date` as of `as_of`. Only days the
+    /// branch is open count; grace days are never charged, recalled loans are
+    /// charged at `recall_daily_fine_cents`, and the fee stops growing at
+    /// `max_fine_cents`.
+    pub fn fine_cents(
+        &self,
+        due_date: NaiveDate,
+        as_of: NaiveDate,
+        calendar: &Calendar,
+        recalled: bool,
+    ) -> i32 {
+        let charged_days = calendar.open_days(due_date, as_of) - i64::from(self.fine_grace_days);
+        if charged_days <= 0 {
+            return 0;
+        }
+        let daily_fine_cents = if recalled {
+            self.recall_daily_fine_cents
+        } else {
+            self.daily_fine_cents
+        };
+        (charged_days * i64::from(daily_fine_cents)).min(self.max_fine_cents.into()) as i32
+    }
+
+    /// Rules naming more of the loan win; the tier counts for more than the
+    /// item type, which counts for more than the branch.
+    fn specificity(&self) -> u8 {
+        (self.tier.is_some() as u8) << 2
+            | (self.item_type.is_some() as u8) << 1
+            | self.branch.is_some() as u8
+    }
+}
+
+/// What a loan is checked against: who borrows what, and where.
+#[derive(Debug, Deserialize)]
+pub struct LoanContext {
+    pub tier: String,
+    pub item_type: String,
+    pub branch: String,
+}
+
+fn validate(rule: &RuleRequest) -> Result<()> {
+    // a rule lends no longer than staff may lend by hand, which also keeps
+    // due dates well within what a date can hold
+    if !(1..=MAX_OVERRIDE_DAYS).contains(&rule.loan_days.into()) {
+        return Err(LibError::ActixError(
+            ErrorBadRequest(format!(
+                "loan_days must be between 1 and {MAX_OVERRIDE_DAYS}"
+            ))
+            .to_string(),
+        )
+        .into());
+    }
+    if i64::from(rule.fine_grace_days) > MAX_OVERRIDE_DAYS {
+        return Err(LibError::ActixError(
+            ErrorBadRequest(format!(
+                "fine_grace_days must be at most {MAX_OVERRIDE_DAYS}"
+            ))
+            .to_string(),
+        )
+        .into());
+    }
+    if rule.max_renewals < 0 || rule.renewal_overdue_days < 0 {
+        return Err(LibError::ActixError(
+            ErrorBadRequest("max_renewals and renewal_overdue_days must not be negative")
+                .to_string(),
+        )
+        .into());
+    }
+    if rule.daily_fine_cents < 0
+        || rule.recall_daily_fine_cents < 0
+        || rule.fine_grace_days < 0
+        || rule.max_fine_cents < 0
+    {
+        return Err(LibError::ActixError(
+            ErrorBadRequest("fine rates, grace days and caps must not be negative").to_string(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+pub fn add_rule(rule: RuleRequest, conn: &mut PgConnection) -> Result<Uuid> {
+    validate(&rule)?;
+    Ok(diesel::insert_into(circulation_rules::table)
+        .values(&rule)
+        .returning(circulation_rules::rule_id)
+        .get_result(conn)?)
+}
+
+pub fn get_rules(conn: &mut PgConnection) -> Result<Vec<CirculationRule>> {
+    Ok(circulation_rules::table
+        .order(circulation_rules::created_at)
+        .select(CirculationRule::as_select())
+        .load(conn)?)
+}
+
+pub fn update_rule(id: Uuid, rule: RuleRequest, conn: &mut PgConnection) -> Result<bool> {
+    validate(&rule)?;
+    let num_updated =
+        diesel::update(circulation_rules::table.filter(circulation_rules::rule_id.eq(id)))
+            .set(&rule)
+            .execute(conn)?;
+    Ok(num_updated > 0)
+}
+
+pub fn delete_rule(id: Uuid, conn: &mut PgConnection) -> Result<bool> {
+    let num_deleted =
+        diesel::delete(circulation_rules::table.filter(circulation_rules::rule_id.eq(id)))
+            .execute(conn)?;
+    Ok(num_deleted > 0)
+}
+
+/// Finds the most specific rule matching the loan, if any.
+pub fn resolve_rule(
+    context: &LoanContext,
+    conn: &mut PgConnection,
+) -> Result<Option<CirculationRule>> {
+    let candidates: Vec<CirculationRule> = circulation_rules::table
+        .filter(
+            circulation_rules::tier
+                .is_null()
+                .or(circulation_rules::tier.eq(&context.tier)),
+        )
+        .filter(
+            circulation_rules::item_type
+                .is_null()
+                .or(circulation_rules::item_type.eq(&context.item_type)),
+        )
+        .filter(
+            circulation_rules::branch
+                .is_null()
+                .or(circulation_rules::branch.eq(&context.branch)),
+        )
+        .select(CirculationRule::as_select())
+        .load(conn)?;
+    Ok(candidates.into_iter().max_by_key(|rule| rule.specificity()))
+}
+
+/// The due date of a loan starting `today`.
+///
+/// A `requested` date replaces the one from the rules, as long as it lies
+/// after today and within [`MAX_OVERRIDE_DAYS`]; callers are expected to have
+/// checked that the caller may override due dates. Either way the loan is
+/// due on a day its branch is open.
+pub fn due_date(
+    context: &LoanContext,
+    requested: Option<NaiveDate>,
+    today: NaiveDate,
+    conn: &mut PgConnection,
+) -> Result<NaiveDate> {
+    if let Some(requested) = requested {
+        if requested <= today || requested > today + Duration::days(MAX_OVERRIDE_DAYS) {
+            return Err(LibError::ActixError(
+                ErrorBadRequest(format!(
+                    "due date must be within {MAX_OVERRIDE_DAYS} days from today"
+                ))
+                .to_string(),
+            )
+            .into());
+        }
+        return next_open_day(&context.branch, requested, conn);
+    }
+
+    let rule = require_rule(context, conn)?;
+    next_open_day(
+        &context.branch,
+        today + Duration::days(rule.loan_days.into()),
+        conn,
+    )
+}
+
+/// Moves `date` past the days `branch` is closed.
+pub fn next_open_day(branch: &str, date: NaiveDate, conn: &mut PgConnection) -> Result<NaiveDate> {
+    Ok(get_calendar(branch, date, conn)?.next_open_day(date))
+}
+
+/// Like [`resolve_rule`], but treats a loan no rule covers as an error.
+pub fn require_rule(context: &LoanContext, conn: &mut PgConnection) -> Result<CirculationRule> {
+    Ok(resolve_rule(context, conn)?.ok_or_else(|| {
+        LibError::DbError(format!(
+            "no circulation rule for tier {}, item type {} at branch {}",
+            context.tier, context.item_type, context.branch
+        ))
+    })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(tier: Option<&str>, item_type: Option<&str>, branch: Option<&str>) -> CirculationRule {
+        CirculationRule {
+            rule_id: Uuid::new_v4(),
+            tier: tier.map(str::to_string),
+            item_type: item_type.map(str::to_string),
+            branch: branch.map(str::to_string),
+            loan_days: 14,
+            created_at: NaiveDateTime::default(),
+            max_renewals: default_max_renewals(),
+            renewal_overdue_days: 0,
+            daily_fine_cents: 25,
+            fine_grace_days: 2,
+            max_fine_cents: 200,
+            recall_daily_fine_cents: 100,
+        }
+    }
+
+    fn request() -> RuleRequest {
+        RuleRequest {
+            tier: None,
+            item_type: None,
+            branch: None,
+            loan_days: 14,
+            max_renewals: default_max_renewals(),
+            renewal_overdue_days: 0,
+            daily_fine_cents: default_daily_fine_cents(),
+            fine_grace_days: 0,
+            max_fine_cents: default_max_fine_cents(),
+            recall_daily_fine_cents: default_recall_daily_fine_cents(),
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 6, day).unwrap()
+    }
+
+    #[test]
+    fn grace_days_are_not_charged() {
+        let rule = rule(None, None, None);
+        let calendar = Calendar::default();
+        assert_eq!(rule.fine_cents(date(10), date(9), &calendar, false), 0);
+        assert_eq!(rule.fine_cents(date(10), date(12), &calendar, false), 0);
+        assert_eq!(rule.fine_cents(date(10), date(13), &calendar, false), 25);
+        assert_eq!(rule.fine_cents(date(10), date(15), &calendar, false), 75);
+    }
+
+    #[test]
+    fn recalled_loans_pay_the_recall_rate_up_to_the_cap() {
+        let rule = rule(None, None, None);
+        let calendar = Calendar::default();
+        assert_eq!(rule.fine_cents(date(10), date(13), &calendar, true), 100);
+        assert_eq!(rule.fine_cents(date(10), date(15), &calendar, true), 200);
+        assert_eq!(rule.fine_cents(date(1), date(30), &calendar, false), 200);
+    }
+
+    #[test]
+    fn tier_outranks_item_type_outranks_branch() {
+        let rules = [
+            rule(None, None, None),
+            rule(None, None, Some("main")),
+            rule(None, Some("dvd"), None),
+            rule(None, Some("dvd"), Some("main")),
+            rule(Some("adult"), None, None),
+            rule(Some("adult"), Some("dvd"), Some("main")),
+        ];
+        for pair in rules.windows(2) {
+            assert!(pair[0].specificity() < pair[1].specificity(), "{pair:?}");
+        }
+    }
+
+    #[test]
+    fn rules_within_bounds_are_accepted() {
+        assert!(validate(&request()).is_ok());
+        assert!(validate(&RuleRequest {
+            loan_days: MAX_OVERRIDE_DAYS as i32,
+            fine_grace_days: MAX_OVERRIDE_DAYS as i32,
+            ..request()
+        })
+        .is_ok());
+    }
+
+    #[test]
+    fn rules_out_of_bounds_are_refused() {
+        for rule in [
+            RuleRequest {
+                loan_days: 0,
+                ..request()
+            },
+            RuleRequest {
+                loan_days: MAX_OVERRIDE_DAYS as i32 + 1,
+                ..request()
+            },
+            RuleRequest {
+                fine_grace_days: MAX_OVERRIDE_DAYS as i32 + 1,
+                ..request()
+            },
+            RuleRequest {
+                max_renewals: -1,
+                ..request()
+            },
+            RuleRequest {
+                daily_fine_cents: -1,
+                ..request()
+            },
+            RuleRequest {
+                max_fine_cents: -1,
+                ..request()
+            },
+        ] {
+            let e = validate(&rule).unwrap_err();
+            assert!(
+                matches!(e.downcast_ref(), Some(LibError::ActixError(_))),
+                "{rule:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{
+    auth::{extractors::Principal, permissions::Permission},
+    db::establish_connection,
+    errors::error_response,
+    notices::{models::NoticeConfig, transport::Message},
+};
+use actix_web::{get, post, web, HttpResponse, Responder};
+use uuid::Uuid;
+
+use super::models::{get_receipt, Receipt, ReceiptFormat, ReceiptQuery, ReceiptRequest};
+
+/// Members may get receipts for their own loans, staff need
+/// [`Permission::ReadLoans`].
+fn require_receipt(principal: &Principal, receipt: &Receipt) -> Result<(), HttpResponse> {
+    match receipt.member_id {
+        Some(member_id) => principal.require_for(member_id, Permission::ReadLoans),
+        None => principal.require(Permission::ReadLoans),
+    }
+}
+
+fn receipt_response(receipt: &Receipt, format: ReceiptFormat) -> HttpResponse {
+    let content_type = match format {
+        ReceiptFormat::Html => "text/html; charset=utf-8",
+        ReceiptFormat::Text => "text/plain; charset=utf-8",
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .body(receipt.render(format))
+}
+
+fn load_receipt(principal: &Principal, loan_ids: &[Uuid]) -> Result<Receipt, HttpResponse> {
+    let mut conn = establish_connection();
+    let receipt = match get_receipt(loan_ids, &mut conn) {
+        Ok(Some(receipt)) => receipt,
+        Ok(None) => return Err(HttpResponse::NotFound().json("loan not found")),
+        Err(e) => return Err(error_response(&e)),
+    };
+    require_receipt(principal, &receipt)?;
+    Ok(receipt)
+}
+
+/// The receipt of one loan, as HTML unless `?format=text` is asked for.
+#[get("/loans/{loan_id}/receipt")]
+async fn loan_receipt(
+    principal: Principal,
+    loan_id: web::Path<Uuid>,
+    query: web::Query<ReceiptQuery>,
+) -> impl Responder {
+    match load_receipt(&principal, &[*loan_id]) {
+        Ok(receipt) => receipt_response(&receipt, query.format),
+        Err(response) => response,
+    }
+}
+
+/// One receipt for several loans of a member, such as a batch checkout.
+#[post("/loans/receipts")]
+async fn batch_receipt(
+    principal: Principal,
+    payload: web::Json<ReceiptRequest>,
+    query: web::Query<ReceiptQuery>,
+) -> impl Responder {
+    match load_receipt(&principal, &payload.loan_ids) {
+        Ok(receipt) => receipt_response(&receipt, query.format),
+        Err(response) => response,
+    }
+}
+
+/// Sends the plain-text receipt of the loans to the member's email address
+/// through the notice transport.
+#[post("/loans/receipts/email")]
+async fn email_receipt(
+    principal: Principal,
+    payload: web::Json<ReceiptRequest>,
+    config: web::Data<NoticeConfig>,
+) -> impl Responder {
+    let Some(transport) = config.transport.clone() else {
+        return HttpResponse::ServiceUnavailable().json("no notice transport is configured");
+    };
+    let receipt = match load_receipt(&principal, &payload.loan_ids) {
+        Ok(receipt) => receipt,
+        Err(response) => return response,
+    };
+    let Some(to) = receipt.email.clone() else {
+        return HttpResponse::BadRequest().json("member has no email address");
+    };
+    let message = Message {
+        to,
+        subject: format!("Your receipt from {}", receipt.library_name),
+        body: receipt.render(ReceiptFormat::Text),
+    };
+    match web::block(move || transport.send(&message)).await {
+        Ok(Ok(())) => HttpResponse::Ok().finish(),
+        Ok(Err(e)) => {
+            HttpResponse::InternalServerError().json(format!("failed to send receipt {e}"))
+        }
+        Err(e) => HttpResponse::InternalServerError().json(format!("{e}")),
+    }
+}
@@ -0,0 +1,135 @@
+use actix_web::error::ErrorBadRequest;
+use anyhow::Result;
+use chrono::NaiveDate;
+use diesel::{prelude::Queryable, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    errors::LibError,
+    fines::models::get_balance,
+    loans::models::LoanStatus,
+    members::models::get_member,
+    schema::{books, loans},
+};
+
+use super::templates::{render_html, render_text};
+
+pub const DEFAULT_LIBRARY_NAME: &str = "Libstack Library";
+
+/// The name printed at the top of receipts, from `LIBRARY_NAME`.
+pub fn library_name() -> String {
+    std::env::var("LIBRARY_NAME").unwrap_or_else(|_| DEFAULT_LIBRARY_NAME.to_string())
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReceiptFormat {
+    #[default]
+    Html,
+    Text,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReceiptQuery {
+    #[serde(default)]
+    pub format: ReceiptFormat,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReceiptRequest {
+    pub loan_ids: Vec<Uuid>,
+}
+
+/// A loan as printed on a receipt: due while it is out, returned once it is
+/// back.
+#[derive(Debug, Queryable, Serialize)]
+pub struct ReceiptLoan {
+    pub loan_id: Uuid,
+    pub member_id: Option<Uuid>,
+    pub title: String,
+    pub author: String,
+    pub loan_date: NaiveDate,
+    pub due_date: NaiveDate,
+    pub return_date: Option<NaiveDate>,
+    pub status: LoanStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Receipt {
+    pub library_name: String,
+    pub printed_on: NaiveDate,
+    /// Missing for returned loans of members who do not keep their history.
+    pub member_id: Option<Uuid>,
+    pub member_name: Option<String>,
+    #[serde(skip)]
+    pub email: Option<String>,
+    pub balance_cents: Option<i64>,
+    pub loans: Vec<ReceiptLoan>,
+}
+
+impl Receipt {
+    pub fn render(&self, format: ReceiptFormat) -> String {
+        match format {
+            ReceiptFormat::Html => render_html(self),
+            ReceiptFormat::Text => render_text(self),
+        }
+    }
+}
+
+/// One receipt for `loan_ids`, in the order given, or `None` when any of the
+/// loans does not exist. The loans have to belong to the same member.
+pub fn get_receipt(loan_ids: &[Uuid], conn: &mut PgConnection) -> Result<Option<Receipt>> {
+    if loan_ids.is_empty() {
+        return Err(LibError::ActixError(ErrorBadRequest("No loans to print").to_string()).into());
+    }
+    let mut loans: Vec<ReceiptLoan> = loans::table
+        .inner_join(books::table)
+        .filter(loans::loan_id.eq_any(loan_ids))
+        .select((
+            loans::loan_id,
+            loans::member_id,
+            books::title,
+            books::author,
+            loans::loan_date,
+            loans::due_date,
+            loans::return_date,
+            loans::status,
+        ))
+        .load(conn)?;
+    if loan_ids
+        .iter()
+        .any(|id| !loans.iter().any(|loan| loan.loan_id == *id))
+    {
+        return Ok(None);
+    }
+    loans.sort_by_key(|loan| loan_ids.iter().position(|id| *id == loan.loan_id));
+
+    let mut member_ids: Vec<Uuid> = loans.iter().filter_map(|loan| loan.member_id).collect();
+    member_ids.sort();
+    member_ids.dedup();
+    if member_ids.len() > 1 {
+        return Err(LibError::ActixError(
+            ErrorBadRequest("Loans on one receipt must belong to one member").to_string(),
+        )
+        .into());
+    }
+
+    let member = match member_ids.first() {
+        Some(&member_id) => get_member(member_id, conn)?,
+        None => None,
+    };
+    let balance_cents = match &member {
+        Some(member) => Some(get_balance(member.member_id, conn)?),
+        None => None,
+    };
+    Ok(Some(Receipt {
+        library_name: library_name(),
+        printed_on: chrono::Utc::now().date_naive(),
+        member_id: member.as_ref().map(|member| member.member_id),
+        member_name: member.as_ref().map(|member| member.name.clone()),
+        email: member.and_then(|member| member.email),
+        balance_cents,
+        loans,
+    }))
+}
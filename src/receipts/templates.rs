@@ -0,0 +1,219 @@
+use crate::{loans::models::LoanStatus, notices::templates::render};
+
+use super::models::{Receipt, ReceiptLoan};
+
+/// A receipt as a page with `{{library}}`, `{{date}}`, `{{member}}`,
+/// `{{balance}}` and `{{lines}}`, where every loan is one `line` with
+/// `{{title}}`, `{{author}}` and `{{status}}`.
+pub struct ReceiptTemplate {
+    pub page: &'static str,
+    pub line: &'static str,
+}
+
+pub const TEXT: ReceiptTemplate = ReceiptTemplate {
+    page: "{{library}}
+Receipt of {{date}}
+Member: {{member}}
+
+{{lines}}
+Balance owed: {{balance}}
+
+Thank you for visiting {{library}}.
+",
+    line: "{{title}} / {{author}}
+  {{status}}
+",
+};
+
+pub const HTML: ReceiptTemplate = ReceiptTemplate {
+    page: "<!DOCTYPE html>
+<html>
+<head><meta charset=\"utf-8\"><title>{{library}} receipt</title></head>
+<body>
+<h1>{{library}}</h1>
+<p>Receipt of {{date}}<br>Member: {{member}}</p>
+<table>
+<tr><th>Title</th><th>Author</th><th>Status</th></tr>
+{{lines}}</table>
+<p>Balance owed: {{balance}}</p>
+<p>Thank you for visiting {{library}}.</p>
+</body>
+</html>
+",
+    line: "<tr><td>{{title}}</td><td>{{author}}</td><td>{{status}}</td></tr>
+",
+};
+
+pub fn render_text(receipt: &Receipt) -> String {
+    render_receipt(&TEXT, receipt, |value| value.to_string())
+}
+
+pub fn render_html(receipt: &Receipt) -> String {
+    render_receipt(&HTML, receipt, escape_html)
+}
+
+fn render_receipt(
+    template: &ReceiptTemplate,
+    receipt: &Receipt,
+    escape: impl Fn(&str) -> String,
+) -> String {
+    let lines: String = receipt
+        .loans
+        .iter()
+        .map(|loan| {
+            render(
+                template.line,
+                &[
+                    ("title", escape(&loan.title)),
+                    ("author", escape(&loan.author)),
+                    ("status", loan_status(loan)),
+                ],
+            )
+        })
+        .collect();
+    render(
+        template.page,
+        &[
+            ("library", escape(&receipt.library_name)),
+            ("date", receipt.printed_on.to_string()),
+            (
+                "member",
+                escape(receipt.member_name.as_deref().unwrap_or("-")),
+            ),
+            (
+                "balance",
+                receipt.balance_cents.map_or("-".to_string(), money),
+            ),
+            // last, so the titles in it are not filled in themselves
+            ("lines", lines),
+        ],
+    )
+}
+
+fn loan_status(loan: &ReceiptLoan) -> String {
+    match (loan.status, loan.return_date) {
+        (LoanStatus::Open | LoanStatus::Overdue, _) => format!("Due {}", loan.due_date),
+        (LoanStatus::Lost, _) => "Declared lost".to_string(),
+        (LoanStatus::ClaimsReturned, _) => "Claimed returned".to_string(),
+        (_, Some(return_date)) => format!("Returned {return_date}"),
+        (_, None) => "Returned".to_string(),
+    }
+}
+
+fn money(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{sign}{}.{:02}", cents.abs() / 100, cents.abs() % 100)
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 6, day).unwrap()
+    }
+
+    fn loan(title: &str, status: LoanStatus, return_date: Option<NaiveDate>) -> ReceiptLoan {
+        ReceiptLoan {
+            loan_id: Uuid::new_v4(),
+            member_id: None,
+            title: title.to_string(),
+            author: "Frank Herbert".to_string(),
+            loan_date: date(1),
+            due_date: date(15),
+            return_date,
+            status,
+        }
+    }
+
+    fn receipt(loans: Vec<ReceiptLoan>) -> Receipt {
+        Receipt {
+            library_name: "Town & Gown Library".to_string(),
+            printed_on: date(10),
+            member_id: None,
+            member_name: Some("Ann <Reader>".to_string()),
+            email: None,
+            balance_cents: Some(1205),
+            loans,
+        }
+    }
+
+    #[test]
+    fn text_receipts_list_every_loan() {
+        let text = render_text(&receipt(vec![
+            loan("Dune", LoanStatus::Open, None),
+            loan("Emma", LoanStatus::Returned, Some(date(9))),
+        ]));
+        assert_eq!(
+            text,
+            "Town & Gown Library
+Receipt of 2023-06-10
+Member: Ann <Reader>
+
+Dune / Frank Herbert
+  Due 2023-06-15
+Emma / Frank Herbert
+  Returned 2023-06-09
+
+Balance owed: 12.05
+
+Thank you for visiting Town & Gown Library.
+"
+        );
+    }
+
+    #[test]
+    fn html_receipts_escape_what_they_print() {
+        let html = render_html(&receipt(vec![loan(
+            "<b>\"Dune\"</b>",
+            LoanStatus::Lost,
+            None,
+        )]));
+        assert!(html.contains("<h1>Town &amp; Gown Library</h1>"));
+        assert!(html.contains("Member: Ann &lt;Reader&gt;"));
+        assert!(html.contains(
+            "<tr><td>&lt;b&gt;&quot;Dune&quot;&lt;/b&gt;</td><td>Frank Herbert</td><td>Declared lost</td></tr>"
+        ));
+    }
+
+    #[test]
+    fn titles_are_not_filled_in() {
+        let text = render_text(&receipt(vec![loan(
+            "The {{library}} Book",
+            LoanStatus::ClaimsReturned,
+            None,
+        )]));
+        assert!(text.contains("The {{library}} Book / Frank Herbert\n  Claimed returned\n"));
+    }
+
+    #[test]
+    fn missing_members_and_balances_print_as_dashes() {
+        let text = render_text(&Receipt {
+            member_name: None,
+            balance_cents: None,
+            ..receipt(vec![loan("Dune", LoanStatus::Returned, None)])
+        });
+        assert!(text.contains("Member: -\n"));
+        assert!(text.contains("Balance owed: -\n"));
+        assert!(text.contains("  Returned\n"));
+    }
+
+    #[test]
+    fn money_is_printed_in_units() {
+        assert_eq!(money(0), "0.00");
+        assert_eq!(money(7), "0.07");
+        assert_eq!(money(1205), "12.05");
+        assert_eq!(money(-150), "-1.50");
+    }
+}
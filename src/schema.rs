@@ -1,9 +1,45 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "fine_entry_type"))]
+    pub struct FineEntryType;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "hold_status"))]
+    pub struct HoldStatus;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "ill_role"))]
+    pub struct IllRole;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "ill_status"))]
+    pub struct IllStatus;
+
     #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "loan_status"))]
     pub struct LoanStatus;
+
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "member_role"))]
+    pub struct MemberRole;
+}
+
+diesel::table! {
+    api_keys (key_id) {
+        key_id -> Uuid,
+        name -> Text,
+        prefix -> Text,
+        key_hash -> Text,
+        scopes -> Array<Nullable<Text>>,
+        created_by -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        agency -> Nullable<Text>,
+    }
 }
 
 diesel::table! {
@@ -14,6 +50,48 @@ diesel::table! {
         publication_year -> Int4,
         isbn -> Nullable<Text>,
         availability_status -> Bool,
+        item_type -> Text,
+        branch -> Text,
+        barcode -> Nullable<Text>,
+        replacement_cost_cents -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    circulation_rules (rule_id) {
+        rule_id -> Uuid,
+        tier -> Nullable<Text>,
+        item_type -> Nullable<Text>,
+        branch -> Nullable<Text>,
+        loan_days -> Int4,
+        created_at -> Timestamp,
+        max_renewals -> Int4,
+        renewal_overdue_days -> Int4,
+        daily_fine_cents -> Int4,
+        fine_grace_days -> Int4,
+        max_fine_cents -> Int4,
+        recall_daily_fine_cents -> Int4,
+    }
+}
+
+diesel::table! {
+    closures (closure_id) {
+        closure_id -> Uuid,
+        branch -> Nullable<Text>,
+        weekday -> Nullable<Int4>,
+        closed_on -> Nullable<Date>,
+        reason -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    credentials (member_id) {
+        member_id -> Uuid,
+        username -> Text,
+        password_hash -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -23,26 +101,222 @@ diesel::table! {
 
     loans (loan_id) {
         loan_id -> Uuid,
-        member_id -> Uuid,
+        member_id -> Nullable<Uuid>,
         book_id -> Uuid,
         loan_date -> Date,
         due_date -> Date,
         return_date -> Nullable<Date>,
         status -> LoanStatus,
+        renewal_count -> Int4,
+        recalled_at -> Nullable<Timestamp>,
+        recall_hold_id -> Nullable<Uuid>,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::FineEntryType;
+
+    fines (entry_id) {
+        entry_id -> Uuid,
+        member_id -> Uuid,
+        loan_id -> Nullable<Uuid>,
+        entry_type -> FineEntryType,
+        amount_cents -> Int4,
+        note -> Nullable<Text>,
+        created_by -> Nullable<Uuid>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::HoldStatus;
+
+    holds (hold_id) {
+        hold_id -> Uuid,
+        book_id -> Uuid,
+        member_id -> Uuid,
+        status -> HoldStatus,
+        placed_at -> Timestamp,
+        ready_at -> Nullable<Timestamp>,
+        pickup_expires_on -> Nullable<Date>,
+        closed_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    ill_messages (message_id) {
+        message_id -> Uuid,
+        ill_request_id -> Uuid,
+        inbound -> Bool,
+        body -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::IllRole;
+    use super::sql_types::IllStatus;
+
+    ill_requests (ill_request_id) {
+        ill_request_id -> Uuid,
+        role -> IllRole,
+        requesting_agency -> Text,
+        supplying_agency -> Nullable<Text>,
+        requesting_request_id -> Text,
+        member_id -> Nullable<Uuid>,
+        book_id -> Nullable<Uuid>,
+        title -> Text,
+        author -> Nullable<Text>,
+        isbn -> Nullable<Text>,
+        status -> IllStatus,
+        due_date -> Nullable<Date>,
+        note -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::LoanStatus;
+
+    loan_statistics (stat_id) {
+        stat_id -> Uuid,
+        book_id -> Uuid,
+        tier -> Text,
+        loan_date -> Date,
+        return_date -> Date,
+        status -> LoanStatus,
+    }
+}
+
+diesel::table! {
+    member_blocks (block_id) {
+        block_id -> Uuid,
+        member_id -> Uuid,
+        block_type -> Text,
+        reason -> Text,
+        created_by -> Nullable<Uuid>,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+        lifted_at -> Nullable<Timestamp>,
     }
 }
 
 diesel::table! {
+    member_merges (merge_id) {
+        merge_id -> Uuid,
+        primary_id -> Uuid,
+        merged_member_id -> Uuid,
+        merged_by -> Nullable<Uuid>,
+        merged_at -> Timestamp,
+        snapshot -> Jsonb,
+        loans_moved -> Int4,
+    }
+}
+
+diesel::table! {
+    member_notes (note_id) {
+        note_id -> Uuid,
+        member_id -> Uuid,
+        body -> Text,
+        created_by -> Nullable<Uuid>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    member_relationships (guardian_id, dependent_id) {
+        guardian_id -> Uuid,
+        dependent_id -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::MemberRole;
+
     members (member_id) {
         member_id -> Uuid,
         name -> Text,
-        email -> Text,
+        email -> Nullable<Text>,
         borrowed -> Int4,
         privilege -> Bool,
+        household_limit -> Nullable<Int4>,
+        anonymized_at -> Nullable<Timestamp>,
+        role -> MemberRole,
+        external_id -> Nullable<Text>,
+        tier -> Text,
+        expires_on -> Nullable<Date>,
+        keep_history -> Bool,
+        card_number -> Nullable<Text>,
+        claims_returned_count -> Int4,
+        dependent -> Bool,
+    }
+}
+
+diesel::table! {
+    notices (notice_id) {
+        notice_id -> Uuid,
+        loan_id -> Uuid,
+        member_id -> Uuid,
+        kind -> Text,
+        due_date -> Date,
+        recipient -> Text,
+        sent -> Bool,
+        error -> Nullable<Text>,
+        attempted_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sessions (session_id) {
+        session_id -> Uuid,
+        member_id -> Uuid,
+        token_hash -> Text,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
     }
 }
 
+diesel::joinable!(api_keys -> members (created_by));
+diesel::joinable!(credentials -> members (member_id));
+diesel::joinable!(fines -> loans (loan_id));
+diesel::joinable!(holds -> books (book_id));
+diesel::joinable!(holds -> members (member_id));
+diesel::joinable!(ill_messages -> ill_requests (ill_request_id));
+diesel::joinable!(ill_requests -> books (book_id));
+diesel::joinable!(ill_requests -> members (member_id));
+diesel::joinable!(loan_statistics -> books (book_id));
 diesel::joinable!(loans -> books (book_id));
+diesel::joinable!(loans -> holds (recall_hold_id));
 diesel::joinable!(loans -> members (member_id));
+diesel::joinable!(notices -> loans (loan_id));
+diesel::joinable!(notices -> members (member_id));
+diesel::joinable!(sessions -> members (member_id));
 
-diesel::allow_tables_to_appear_in_same_query!(books, loans, members,);
+diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    books,
+    circulation_rules,
+    closures,
+    credentials,
+    fines,
+    holds,
+    ill_messages,
+    ill_requests,
+    loan_statistics,
+    loans,
+    member_blocks,
+    member_merges,
+    member_notes,
+    member_relationships,
+    members,
+    notices,
+    sessions,
+);